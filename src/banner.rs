@@ -0,0 +1,98 @@
+//! Render text as large ASCII-art letters for `#banner:` lines.
+
+use failure::{format_err, Error};
+
+/// Number of rows of every glyph.
+const HEIGHT: usize = 5;
+
+/// Glyphs of the bundled 5 row font, `X` marks a filled cell.
+const GLYPHS: &[(char, [&str; HEIGHT])] = &[
+    ('A', [" XXX ", "X   X", "XXXXX", "X   X", "X   X"]),
+    ('B', ["XXXX ", "X   X", "XXXX ", "X   X", "XXXX "]),
+    ('C', [" XXXX", "X    ", "X    ", "X    ", " XXXX"]),
+    ('D', ["XXXX ", "X   X", "X   X", "X   X", "XXXX "]),
+    ('E', ["XXXXX", "X    ", "XXXX ", "X    ", "XXXXX"]),
+    ('F', ["XXXXX", "X    ", "XXXX ", "X    ", "X    "]),
+    ('G', [" XXXX", "X    ", "X  XX", "X   X", " XXX "]),
+    ('H', ["X   X", "X   X", "XXXXX", "X   X", "X   X"]),
+    ('I', ["XXX", " X ", " X ", " X ", "XXX"]),
+    ('J', ["  XXX", "   X ", "   X ", "X  X ", " XX  "]),
+    ('K', ["X   X", "X  X ", "XXX  ", "X  X ", "X   X"]),
+    ('L', ["X    ", "X    ", "X    ", "X    ", "XXXXX"]),
+    ('M', ["X   X", "XX XX", "X X X", "X   X", "X   X"]),
+    ('N', ["X   X", "XX  X", "X X X", "X  XX", "X   X"]),
+    ('O', [" XXX ", "X   X", "X   X", "X   X", " XXX "]),
+    ('P', ["XXXX ", "X   X", "XXXX ", "X    ", "X    "]),
+    ('Q', [" XXX ", "X   X", "X X X", "X  X ", " XX X"]),
+    ('R', ["XXXX ", "X   X", "XXXX ", "X  X ", "X   X"]),
+    ('S', [" XXXX", "X    ", " XXX ", "    X", "XXXX "]),
+    ('T', ["XXXXX", "  X  ", "  X  ", "  X  ", "  X  "]),
+    ('U', ["X   X", "X   X", "X   X", "X   X", " XXX "]),
+    ('V', ["X   X", "X   X", "X   X", " X X ", "  X  "]),
+    ('W', ["X   X", "X   X", "X X X", "XX XX", "X   X"]),
+    ('X', ["X   X", " X X ", "  X  ", " X X ", "X   X"]),
+    ('Y', ["X   X", " X X ", "  X  ", "  X  ", "  X  "]),
+    ('Z', ["XXXXX", "   X ", "  X  ", " X   ", "XXXXX"]),
+    ('0', [" XXX ", "X  XX", "X X X", "XX  X", " XXX "]),
+    ('1', [" X ", "XX ", " X ", " X ", "XXX"]),
+    ('2', [" XXX ", "X   X", "  XX ", " X   ", "XXXXX"]),
+    ('3', ["XXXX ", "    X", " XXX ", "    X", "XXXX "]),
+    ('4', ["X   X", "X   X", "XXXXX", "    X", "    X"]),
+    ('5', ["XXXXX", "X    ", "XXXX ", "    X", "XXXX "]),
+    ('6', [" XXX ", "X    ", "XXXX ", "X   X", " XXX "]),
+    ('7', ["XXXXX", "    X", "   X ", "  X  ", "  X  "]),
+    ('8', [" XXX ", "X   X", " XXX ", "X   X", " XXX "]),
+    ('9', [" XXX ", "X   X", " XXXX", "    X", " XXX "]),
+    (' ', ["   ", "   ", "   ", "   ", "   "]),
+    ('!', ["X", "X", "X", " ", "X"]),
+    ('?', ["XXX ", "   X", " XX ", "    ", " X  "]),
+    ('.', [" ", " ", " ", " ", "X"]),
+    (',', ["  ", "  ", "  ", " X", "X "]),
+    (':', [" ", "X", " ", "X", " "]),
+    ('-', ["    ", "    ", "XXXX", "    ", "    "]),
+    ('_', ["    ", "    ", "    ", "    ", "XXXX"]),
+    ('/', ["    X", "   X ", "  X  ", " X   ", "X    "]),
+];
+
+/// Bundled fonts, all of them render the glyphs above with a different fill.
+const FONTS: &[(&str, char)] = &[("block", '█'), ("hash", '#'), ("dots", '.')];
+
+/// Names of the bundled fonts.
+pub fn fonts() -> Vec<&'static str> {
+    FONTS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Render `text` with the font `font`, returning one string per row.
+pub fn render(text: &str, font: &str) -> Result<Vec<String>, Error> {
+    let fill = FONTS
+        .iter()
+        .find(|(name, _)| *name == font)
+        .map(|(_, fill)| *fill)
+        .ok_or_else(|| {
+            format_err!(
+                "unknown banner font `{}` (expected one of: {})",
+                font,
+                fonts().join(", ")
+            )
+        })?;
+
+    let mut rows = vec![String::new(); HEIGHT];
+    for (index, char) in text.trim().to_uppercase().chars().enumerate() {
+        let glyph = GLYPHS
+            .iter()
+            .find(|(glyph_char, _)| *glyph_char == char)
+            .map(|(_, glyph)| glyph)
+            .ok_or_else(|| format_err!("banner font has no glyph for `{}`", char))?;
+        for (row, glyph_row) in rows.iter_mut().zip(glyph.iter()) {
+            if index > 0 {
+                row.push(' ');
+            }
+            row.extend(glyph_row.chars().map(|c| if c == 'X' { fill } else { ' ' }));
+        }
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| row.trim_end().to_string())
+        .collect())
+}
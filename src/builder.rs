@@ -0,0 +1,150 @@
+//! Build a cast in code instead of writing a scenario, see
+//! [`ScenarioBuilder`].
+
+use crate::{Entry, RenderOptions, Renderer, ScenarioHeader};
+use failure::Error;
+
+/// A line of the scenario being built.
+enum Line {
+    /// a command typed after the prompt, like `$ ` lines
+    Command(String),
+    /// a line printed as is
+    Output(String),
+    /// clear the screen, like `--` lines
+    Clear,
+    /// wait for this many seconds, like `#timeout:` lines
+    Timeout(f64),
+}
+
+/// Builds a cast line by line, like a scenario does, eg.:
+///
+/// ```
+/// use asciinema_scenario::ScenarioBuilder;
+///
+/// let entries = ScenarioBuilder::new()
+///     .width(120)
+///     .step(0.08)
+///     .prompt("$ ")
+///     .line_command("ls")
+///     .line_output("a b c")
+///     .clear()
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// The header is the default one of a scenario until changed, it applies to
+/// every line whatever the order the methods are called in.
+pub struct ScenarioBuilder {
+    header: ScenarioHeader,
+    lines: Vec<Line>,
+    seed: Option<u64>,
+}
+
+impl Default for ScenarioBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScenarioBuilder {
+    pub fn new() -> Self {
+        ScenarioBuilder {
+            header: ScenarioHeader::default(),
+            lines: vec![],
+            seed: None,
+        }
+    }
+
+    /// Use this header, eg. parsed with [`crate::parse_header`].
+    pub fn header(mut self, header: ScenarioHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Number of columns of the terminal.
+    pub fn width(mut self, width: u32) -> Self {
+        self.header.width = width;
+        self
+    }
+
+    /// Number of rows of the terminal.
+    pub fn height(mut self, height: u32) -> Self {
+        self.header.height = height;
+        self
+    }
+
+    /// Time in seconds between two typed characters.
+    pub fn step(mut self, step: f64) -> Self {
+        self.header.step = step;
+        self
+    }
+
+    /// Prompt printed before every command.
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.header.prompt = prompt.to_string();
+        self
+    }
+
+    /// Seed for the random number generator used by typing profiles of the
+    /// header.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Type a command after the prompt, like a `$ ` line.
+    pub fn line_command(mut self, command: &str) -> Self {
+        self.lines.push(Line::Command(command.to_string()));
+        self
+    }
+
+    /// Print a line as is, unlike output lines of a scenario it is neither
+    /// taken for a directive nor styled by markup.
+    pub fn line_output(mut self, output: &str) -> Self {
+        self.lines.push(Line::Output(output.to_string()));
+        self
+    }
+
+    /// Clear the screen, like a `--` line.
+    pub fn clear(mut self) -> Self {
+        self.lines.push(Line::Clear);
+        self
+    }
+
+    /// Wait for `seconds`, like a `#timeout:` line.
+    pub fn timeout(mut self, seconds: f64) -> Self {
+        self.lines.push(Line::Timeout(seconds));
+        self
+    }
+
+    /// The entries of the cast, rendered like a scenario with the same
+    /// lines.
+    pub fn build(self) -> Result<Vec<Entry>, Error> {
+        let options = RenderOptions {
+            seed: self.seed,
+            ..RenderOptions::default()
+        };
+        let mut renderer = Renderer::new(self.header, &options)?;
+        for line in self.lines {
+            match line {
+                Line::Command(command) => {
+                    renderer.start_line(Some(true));
+                    renderer.console_line("", &command)?;
+                }
+                Line::Output(output) => {
+                    renderer.start_line(Some(false));
+                    renderer.output(output, true);
+                }
+                Line::Clear => {
+                    renderer.start_line(None);
+                    renderer.clear()?;
+                }
+                Line::Timeout(seconds) => {
+                    renderer.start_line(Some(false));
+                    renderer.timeout(seconds)?;
+                }
+            }
+        }
+        Ok(renderer.finish(vec![])?.entries)
+    }
+}
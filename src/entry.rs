@@ -0,0 +1,80 @@
+//! Events of an asciicast v2 recording.
+//!
+//! Same as the entries of the `asciicast` crate, which only knows about input
+//! and output events, with the marker and resize events of the format.
+
+use serde::de::{Deserializer, Error as DeserializeError, Unexpected};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum EventType {
+    /// data sent to the recorded shell, `"i"`
+    Input,
+    /// data printed on the terminal, `"o"`
+    Output,
+    /// a marker (eg. a chapter) players can jump to, the data is its label,
+    /// `"m"`
+    Marker,
+    /// a resize of the terminal, the data is `<columns>x<rows>`, `"r"`
+    Resize,
+}
+
+impl EventType {
+    fn code(&self) -> &'static str {
+        match self {
+            EventType::Input => "i",
+            EventType::Output => "o",
+            EventType::Marker => "m",
+            EventType::Resize => "r",
+        }
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        [
+            EventType::Input,
+            EventType::Output,
+            EventType::Marker,
+            EventType::Resize,
+        ]
+        .iter()
+        .find(|event_type| event_type.code() == code)
+        .copied()
+        .ok_or_else(|| DeserializeError::invalid_value(Unexpected::Str(&code), &"i, o, m or r"))
+    }
+}
+
+/// An event of the recording, written as a `[time, type, data]` array.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Entry {
+    /// seconds since the beginning of the recording
+    pub time: f64,
+    pub event_type: EventType,
+    pub event_data: String,
+}
+
+impl Serialize for Entry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.time, self.event_type, &self.event_data).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (time, event_type, event_data) = Deserialize::deserialize(deserializer)?;
+        Ok(Entry {
+            time,
+            event_type,
+            event_data,
+        })
+    }
+}
@@ -0,0 +1,216 @@
+//! Render the generated cast into an animated GIF (`--gif`).
+//!
+//! The entries are played back into a virtual terminal, every time the
+//! screen changes it is drawn as an SVG (with the font and theme of the SVG
+//! preview), rasterized with resvg and handed to gifski which encodes the
+//! animation.
+
+use crate::{parse_size, theme, Entry, EventType, ScenarioHeader, Theme};
+use failure::{format_err, Error};
+use imgref::ImgVec;
+use resvg::{tiny_skia, usvg};
+use rgb::RGBA8;
+use std::fs::File;
+use std::thread;
+use svg::node::element::{Rectangle, Text as TextElement};
+use svg::node::Text;
+use svg::Document;
+
+/// How long the last frame stays visible, in seconds.
+const FINAL_FRAME_DURATION: f64 = 1.0;
+
+pub struct GifOptions {
+    /// frames per second the screen is sampled at
+    pub fps: u32,
+    /// how many times the animation plays, 0 means forever
+    pub loops: u16,
+}
+
+fn color(color: vt100::Color, bold: bool, default: &str, theme: &Theme) -> String {
+    match color {
+        vt100::Color::Default => default.to_string(),
+        // bold text in one of the 8 standard colors is shown bright
+        vt100::Color::Idx(index) if index < 8 && bold => {
+            theme.palette[index as usize + 8].to_string()
+        }
+        vt100::Color::Idx(index) if index < 16 => theme.palette[index as usize].to_string(),
+        vt100::Color::Idx(index) if index < 232 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                level(index / 36),
+                level(index / 6 % 6),
+                level(index % 6)
+            )
+        }
+        vt100::Color::Idx(index) => {
+            let gray = 8 + (index - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+        vt100::Color::Rgb(red, green, blue) => format!("#{:02x}{:02x}{:02x}", red, green, blue),
+    }
+}
+
+/// Draw the screen of the virtual terminal as an SVG document of `size`
+/// cells, the largest size of the terminal during the cast.
+fn screen_svg(
+    screen: &vt100::Screen,
+    size: (u32, u32),
+    header: &ScenarioHeader,
+    theme: &Theme,
+) -> Document {
+    let cell_width = header.svg_cell_width();
+    let line_height = header.svg_line_height();
+    let mut document = Document::new()
+        .set("xmlns", "http://www.w3.org/2000/svg")
+        .set("width", size.0 * cell_width)
+        .set("height", size.1 * line_height)
+        .add(
+            Rectangle::new()
+                .set("width", "100%")
+                .set("height", "100%")
+                .set("fill", theme.background),
+        );
+
+    let (rows, cols) = screen.size();
+    for row in 0..rows {
+        for col in 0..cols {
+            let cell = match screen.cell(row, col) {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let (mut fg, mut bg) = (
+                color(cell.fgcolor(), cell.bold(), theme.foreground, theme),
+                color(cell.bgcolor(), false, theme.background, theme),
+            );
+            if cell.inverse() {
+                std::mem::swap(&mut fg, &mut bg);
+            }
+            let x = u32::from(col) * cell_width;
+            let y = u32::from(row) * line_height;
+            if bg != theme.background {
+                document = document.add(
+                    Rectangle::new()
+                        .set("x", x)
+                        .set("y", y)
+                        .set("width", cell_width)
+                        .set("height", line_height)
+                        .set("fill", bg),
+                );
+            }
+            let contents = cell.contents();
+            if contents.trim().is_empty() {
+                continue;
+            }
+            let mut text = TextElement::new()
+                .set("x", x)
+                .set("y", y + line_height)
+                .set("font-family", header.font_family.as_str())
+                .set("font-size", header.font_size)
+                .set("fill", fg)
+                .add(Text::new(html_escape::encode_safe(&contents)));
+            if cell.bold() {
+                text = text.set("font-weight", "bold");
+            }
+            document = document.add(text);
+        }
+    }
+    document
+}
+
+fn rasterize(document: &Document, options: &usvg::Options) -> Result<ImgVec<RGBA8>, Error> {
+    let tree = usvg::Tree::from_str(&document.to_string(), options)?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| format_err!("can not create a {}x{} image", size.width(), size.height()))?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    let pixels = pixmap
+        .data()
+        .chunks(4)
+        .map(|pixel| RGBA8::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+        .collect();
+    Ok(ImgVec::new(
+        pixels,
+        size.width() as usize,
+        size.height() as usize,
+    ))
+}
+
+/// Play `entries` back in a terminal of the size of the `header` (resized by
+/// the `"r"` events) and write the animation to `path`.
+pub fn render(
+    path: &str,
+    entries: &[Entry],
+    header: &ScenarioHeader,
+    options: &GifOptions,
+) -> Result<(), Error> {
+    if options.fps == 0 {
+        return Err(format_err!("--gif-fps must be at least 1"));
+    }
+    let theme = theme(&header.theme)?;
+    // every frame has the largest size of the terminal
+    let mut size = (header.width, header.height);
+    for entry in entries {
+        if entry.event_type == EventType::Resize {
+            let (width, height) = parse_size(&entry.event_data)?;
+            size = (size.0.max(width), size.1.max(height));
+        }
+    }
+    // the frames keep the size of the SVG preview instead of being scaled down
+    let (collector, writer) = gifski::new(gifski::Settings {
+        width: Some(size.0 * header.svg_cell_width()),
+        height: Some(size.1 * header.svg_line_height()),
+        quality: 90,
+        fast: false,
+        repeat: match options.loops {
+            0 => gifski::Repeat::Infinite,
+            loops => gifski::Repeat::Finite(loops),
+        },
+    })?;
+    let mut svg_options = usvg::Options::default();
+    svg_options.fontdb_mut().load_system_fonts();
+
+    let duration = entries.last().map(|entry| entry.time).unwrap_or(0.0) + FINAL_FRAME_DURATION;
+    let frames = (duration * f64::from(options.fps)).ceil() as usize;
+
+    thread::scope(|scope| {
+        let frames = scope.spawn(move || -> Result<(), Error> {
+            let mut parser = vt100::Parser::new(header.height as u16, header.width as u16, 0);
+            let mut remaining = entries.iter().peekable();
+            let mut previous: Option<Vec<u8>> = None;
+            let mut index = 0;
+            for frame in 0..=frames {
+                let time = frame as f64 / f64::from(options.fps);
+                while let Some(entry) = remaining.next_if(|entry| entry.time <= time) {
+                    match entry.event_type {
+                        EventType::Output => parser.process(entry.event_data.as_bytes()),
+                        EventType::Resize => {
+                            let (width, height) = parse_size(&entry.event_data)?;
+                            parser.set_size(height as u16, width as u16);
+                        }
+                        _ => {}
+                    }
+                }
+                // only add a frame when the screen changed, except the last one
+                // which keeps the final screen visible for a moment
+                let state = parser.screen().contents_formatted();
+                if previous.as_ref() == Some(&state) && frame != frames {
+                    continue;
+                }
+                previous = Some(state);
+                let image = rasterize(
+                    &screen_svg(parser.screen(), size, header, theme),
+                    &svg_options,
+                )?;
+                collector.add_frame_rgba(index, image, time)?;
+                index += 1;
+            }
+            Ok(())
+        });
+        writer.write(File::create(path)?, &mut gifski::progress::NoProgress {})?;
+        frames
+            .join()
+            .map_err(|_| format_err!("rendering gif frames failed"))?
+    })
+}
@@ -0,0 +1,4678 @@
+//! Turn a scenario into an asciicast recording.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let scenario = File::open("demo.scenario").unwrap();
+//! let entries = asciinema_scenario::render_scenario(BufReader::new(scenario)).unwrap();
+//! ```
+//!
+//! [`render`] gives access to everything else collected while rendering (the
+//! header, the SVG preview lines, chapters, ...) and takes the same options
+//! as the command line. [`ScenarioBuilder`] builds a cast in code instead.
+
+use failure::{format_err, Error};
+use html_escape::encode_safe;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::ser::Formatter;
+use serde_json::{from_str, to_string, Serializer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use svg::node::element::{Element, Group, Mask, Rectangle, Style, Text as TextElement};
+use svg::node::{NodeDefaultHash, Text, Value};
+use svg::{Document, Node};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[cfg(feature = "banner")]
+mod banner;
+mod builder;
+mod entry;
+#[cfg(feature = "gif")]
+pub mod gif;
+#[cfg(feature = "png")]
+pub mod png;
+
+pub use builder::ScenarioBuilder;
+pub use entry::{Entry, EventType};
+
+const TSPAN_TAG: &str = "tspan";
+
+#[derive(Clone, Debug)]
+pub struct TSpan {
+    inner: Element,
+}
+
+impl TSpan {
+    pub fn new() -> Self {
+        TSpan {
+            inner: Element::new(TSPAN_TAG),
+        }
+    }
+
+    pub fn append<T>(mut self, node: T) -> Self
+    where
+        T: Node,
+    {
+        Node::append(&mut self, node);
+        self
+    }
+
+    #[inline]
+    pub fn set<T, U>(mut self, name: T, value: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<Value>,
+    {
+        Node::assign(&mut self, name, value);
+        self
+    }
+
+    #[inline]
+    pub fn get_inner(&self) -> &Element {
+        &self.inner
+    }
+}
+
+impl Default for TSpan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeDefaultHash for TSpan {
+    fn default_hash(&self, state: &mut DefaultHasher) {
+        self.inner.default_hash(state);
+    }
+}
+
+impl Node for TSpan {
+    fn append<T>(&mut self, node: T)
+    where
+        T: Node,
+    {
+        self.inner.append(node);
+    }
+
+    fn assign<T, U>(&mut self, name: T, value: U)
+    where
+        T: Into<String>,
+        U: Into<Value>,
+    {
+        self.inner.assign(name, value);
+    }
+}
+
+impl Display for TSpan {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        self.inner.fmt(formatter)
+    }
+}
+
+impl From<TSpan> for Element {
+    fn from(val: TSpan) -> Self {
+        val.inner
+    }
+}
+
+/// A line of the SVG preview.
+#[derive(Clone, Debug)]
+pub enum PreviewLine {
+    /// a typed command with its prompt prefix (eg. `(nix-shell) `) and prompt,
+    /// `comment` is where its `#` comment starts and `rprompt` is shown on
+    /// the right of the row
+    Console {
+        prefix: String,
+        prompt: String,
+        command: String,
+        comment: Option<usize>,
+        rprompt: Option<String>,
+    },
+    /// a line printed as is
+    Output(String),
+    /// a line made of spans of text, each with an optional CSS class
+    Styled(Vec<(Option<String>, String)>),
+    /// the terminal is resized (`#resize:`), the following lines wrap at
+    /// the new width, it takes no line itself
+    Resize { width: u32, height: u32 },
+    /// the screen is cleared (`--` or `#chapter:`), it takes no line itself
+    Clear,
+    /// a line not ended by a newline (`\c`), the next line continues on the
+    /// same row
+    Unterminated(Box<PreviewLine>),
+}
+
+impl PreviewLine {
+    /// Number of columns the line takes on the screen, tabs move to the next
+    /// multiple of `tabstop`.
+    fn width(&self, tabstop: usize) -> usize {
+        match self {
+            PreviewLine::Console {
+                prefix,
+                prompt,
+                command,
+                ..
+            } => columns(&format!("{}{}{}", prefix, prompt, command), tabstop),
+            PreviewLine::Output(line) => columns(line, tabstop),
+            PreviewLine::Styled(spans) => columns(
+                &spans
+                    .iter()
+                    .map(|(_, text)| text.as_str())
+                    .collect::<String>(),
+                tabstop,
+            ),
+            PreviewLine::Resize { .. } | PreviewLine::Clear => 0,
+            PreviewLine::Unterminated(preview_line) => preview_line.width(tabstop),
+        }
+    }
+
+    /// The line itself if it is ended by a newline, otherwise the line as
+    /// [`PreviewLine::Unterminated`].
+    fn terminated(self, newline: bool) -> Self {
+        if newline {
+            self
+        } else {
+            PreviewLine::Unterminated(Box::new(self))
+        }
+    }
+
+    /// The line as spans of text, each with an optional CSS class: the prompt
+    /// prefix and the comment of a command are styled as set in the `header`
+    /// and printed lines are colored by their SGR escape sequences. The right
+    /// prompt of a command is aligned on the last of the `columns`, unless
+    /// the command reaches it.
+    fn spans(self, header: &ScenarioHeader, columns: usize) -> Vec<(Option<String>, String)> {
+        let width = self.width(header.tabstop);
+        match self {
+            PreviewLine::Console {
+                prefix,
+                prompt,
+                mut command,
+                comment,
+                rprompt,
+            } => {
+                let comment = comment.map(|comment| command.split_off(comment));
+                let mut spans = vec![(sgr_class(&header.prompt_sgr), prefix), (None, prompt)];
+                spans.extend(sgr_spans(&command));
+                spans.extend(comment.map(|comment| (sgr_class(&header.comment_sgr), comment)));
+                if let Some(rprompt) = rprompt {
+                    let rprompt = sgr_spans(&rprompt);
+                    let rprompt_width = PreviewLine::Styled(rprompt.clone()).width(header.tabstop);
+                    if width + rprompt_width < columns {
+                        spans.push((None, " ".repeat(columns - width - rprompt_width)));
+                        spans.extend(rprompt);
+                    }
+                }
+                spans
+            }
+            PreviewLine::Output(line) => sgr_spans(&line),
+            PreviewLine::Styled(spans) => spans,
+            PreviewLine::Resize { .. } | PreviewLine::Clear => vec![],
+            PreviewLine::Unterminated(preview_line) => preview_line.spans(header, columns),
+        }
+    }
+}
+
+/// Names of the 8 standard terminal colors, in the order of their SGR codes.
+const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// asciinema's default palette of the 16 terminal colors.
+const PALETTE: [&str; 16] = [
+    "#000000", "#dd3c69", "#4ebf22", "#ddaf3c", "#26b0d7", "#b954e1", "#54e1b9", "#d9d9d9",
+    "#4d4d4d", "#dd3c69", "#4ebf22", "#ddaf3c", "#26b0d7", "#b954e1", "#54e1b9", "#ffffff",
+];
+const BACKGROUND: &str = "#121314";
+const FOREGROUND: &str = "#cccccc";
+
+/// Colors of the SVG preview.
+pub struct Theme {
+    pub name: &'static str,
+    pub background: &'static str,
+    pub foreground: &'static str,
+    /// the 16 terminal colors
+    pub palette: [&'static str; 16],
+}
+
+/// Bundled themes of the SVG preview, the first one is the default.
+pub const THEMES: &[Theme] = &[
+    Theme {
+        name: "dark",
+        background: BACKGROUND,
+        foreground: FOREGROUND,
+        palette: PALETTE,
+    },
+    Theme {
+        name: "light",
+        background: "#fafafa",
+        foreground: "#383a42",
+        palette: [
+            "#383a42", "#e45649", "#50a14f", "#c18401", "#0184bc", "#a626a4", "#0997b3", "#fafafa",
+            "#4f525e", "#e06c75", "#98c379", "#e5c07b", "#61afef", "#c678dd", "#56b6c2", "#ffffff",
+        ],
+    },
+    Theme {
+        name: "solarized",
+        background: "#002b36",
+        foreground: "#839496",
+        palette: [
+            "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+            "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+        ],
+    },
+];
+
+/// The bundled theme `name`.
+fn theme(name: &str) -> Result<&'static Theme, Error> {
+    THEMES
+        .iter()
+        .find(|theme| theme.name == name)
+        .ok_or_else(|| {
+            format_err!(
+                "unknown theme `{}` (expected one of: {})",
+                name,
+                THEMES
+                    .iter()
+                    .map(|theme| theme.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// Split a printed line into spans by its SGR escape sequences, the 16
+/// foreground colors become `fg-N` classes and bold the `bold` class. Other
+/// escape sequences are left out.
+fn sgr_spans(line: &str) -> Vec<(Option<String>, String)> {
+    let mut spans = vec![];
+    let mut text = String::new();
+    let mut sgr = Sgr::default();
+    let mut class = None;
+    let mut chars = line.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '\x1b' {
+            text.push(char);
+            continue;
+        }
+        if chars.next_if_eq(&'[').is_none() {
+            chars.next();
+            continue;
+        }
+        let mut params = String::new();
+        let mut end = None;
+        for char in chars.by_ref() {
+            if ('\x40'..='\x7e').contains(&char) {
+                end = Some(char);
+                break;
+            }
+            params.push(char);
+        }
+        if end != Some('m') {
+            continue;
+        }
+        sgr.apply(&params);
+        let next_class = sgr.class();
+        if next_class != class {
+            if !text.is_empty() {
+                spans.push((class, std::mem::take(&mut text)));
+            }
+            class = next_class;
+        }
+    }
+    spans.push((class, text));
+    spans
+}
+
+/// The graphic rendition set by SGR escape sequences, as far as the SVG
+/// preview shows it.
+#[derive(Default)]
+struct Sgr {
+    fg: Option<usize>,
+    bold: bool,
+    dim: bool,
+}
+
+impl Sgr {
+    /// Apply the parameters of an SGR escape sequence (eg. `1;32`).
+    fn apply(&mut self, params: &str) {
+        let mut codes = params.split(';').map(|code| code.parse().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Sgr::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                30..=37 => self.fg = Some(code - 30),
+                39 => self.fg = None,
+                90..=97 => self.fg = Some(code - 90 + 8),
+                // 256 and true colors are not supported, skip their arguments
+                38 | 48 => match codes.next() {
+                    Some(5) => {
+                        codes.next();
+                    }
+                    Some(2) => {
+                        codes.nth(2);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// CSS classes of the text, `None` for the default rendition.
+    fn class(&self) -> Option<String> {
+        let classes: Vec<String> = self
+            .fg
+            // bold text in one of the 8 standard colors is shown bright
+            .map(|fg| if self.bold && fg < 8 { fg + 8 } else { fg })
+            .map(|fg| format!("fg-{}", fg))
+            .into_iter()
+            .chain(Some("bold".to_string()).filter(|_| self.bold))
+            .chain(Some("dim".to_string()).filter(|_| self.dim))
+            .collect();
+        Some(classes.join(" ")).filter(|classes| !classes.is_empty())
+    }
+}
+
+/// CSS classes of the text written after the SGR escape sequence with
+/// `params` (eg. `32` gives `fg-2`).
+fn sgr_class(params: &str) -> Option<String> {
+    let mut sgr = Sgr::default();
+    sgr.apply(params);
+    sgr.class()
+}
+
+/// CSS of the SVG preview, defining the colors of the `fg-N` classes.
+fn svg_style(theme: &Theme) -> String {
+    let mut style = format!(
+        ".background {{ fill: {}; }} text {{ fill: {}; }} .bold {{ font-weight: bold; }} .dim {{ opacity: 0.6; }} .hint rect {{ fill: {}; }} .hint text {{ fill: {}; }}",
+        theme.background, theme.foreground, theme.foreground, theme.background
+    );
+    for (index, color) in theme.palette.iter().enumerate() {
+        style.push_str(&format!(" .fg-{} {{ fill: {}; }}", index, color));
+    }
+    style
+}
+
+/// Index of the color `name` in the terminal palette.
+fn color_index(name: &str) -> Result<usize, Error> {
+    COLORS
+        .iter()
+        .position(|color| *color == name)
+        .ok_or_else(|| {
+            format_err!(
+                "unknown color `{}` (expected one of: {})",
+                name,
+                COLORS.join(", ")
+            )
+        })
+}
+
+/// SGR parameters of the markup tags of output lines besides the colors.
+const MARKUP_STYLES: &[(&str, &str)] = &[("bold", "1"), ("dim", "2")];
+
+/// Translate the markup of an output line into SGR escape sequences: a color
+/// or style name in brackets (eg. `[red]`) starts it, `[/]` resets it and
+/// `\[` is a literal `[`. Brackets around anything else are left as is.
+fn markup(line: &str) -> String {
+    let mut translated = String::new();
+    let mut styled = false;
+    let mut rest = line;
+    while let Some(start) = rest.find(['[', '\\']) {
+        translated.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(stripped) = rest.strip_prefix("\\[") {
+            translated.push('[');
+            rest = stripped;
+            continue;
+        }
+        let tag = rest
+            .strip_prefix('[')
+            .and_then(|tag| tag.split_once(']'))
+            .and_then(|(name, stripped)| {
+                let params = match COLORS.iter().position(|color| *color == name) {
+                    Some(index) => (30 + index).to_string(),
+                    None if name == "/" => "0".to_string(),
+                    None => MARKUP_STYLES
+                        .iter()
+                        .find(|(style, _)| *style == name)
+                        .map(|(_, params)| params.to_string())?,
+                };
+                Some((params, stripped))
+            });
+        match tag {
+            Some((params, stripped)) => {
+                styled = params != "0";
+                translated.push_str(&format!("\x1b[{}m", params));
+                rest = stripped;
+            }
+            None => {
+                translated.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    translated.push_str(rest);
+    // the style doesn't leak into the next lines
+    if styled {
+        translated.push_str("\x1b[0m");
+    }
+    translated
+}
+
+/// Colors of the log levels of `#log:` lines unless set in the header.
+const LOG_COLORS: &[(&str, &str)] = &[
+    ("DEBUG", "blue"),
+    ("INFO", "green"),
+    ("WARN", "yellow"),
+    ("ERROR", "red"),
+];
+
+/// Render a `#log:` line (eg. `INFO starting server`) as a log record with
+/// its level colored, optionally prefixed with a timestamp derived from
+/// `time`. Returns the cast output and the matching preview line.
+fn log_line(
+    header: &ScenarioHeader,
+    time: Micros,
+    record: &str,
+) -> Result<(String, PreviewLine), Error> {
+    let record = record.trim();
+    let (level, message) = record.split_at(record.find(' ').unwrap_or(record.len()));
+    let level = level.to_uppercase();
+    let color = match header.log_colors.get(&level) {
+        Some(color) => color.as_str(),
+        None => LOG_COLORS
+            .iter()
+            .find(|(log_level, _)| *log_level == level)
+            .map(|(_, color)| *color)
+            .ok_or_else(|| format_err!("unknown log level `{}`", level))?,
+    };
+    let index = color_index(color)?;
+
+    let mut spans = vec![];
+    if header.log_timestamp {
+        let millis = time / 1_000;
+        spans.push((
+            None,
+            format!(
+                "{:02}:{:02}:{:02}.{:03} ",
+                millis / 3_600_000,
+                millis / 60_000 % 60,
+                millis / 1_000 % 60,
+                millis % 1_000
+            ),
+        ));
+    }
+    spans.push((Some(format!("fg-{}", index)), format!("{:<5}", level)));
+    spans.push((None, message.to_string()));
+
+    let output = spans
+        .iter()
+        .map(|(class, text)| match class {
+            Some(_) => format!("\x1b[{}m{}\x1b[0m", 30 + index, text),
+            None => text.clone(),
+        })
+        .collect();
+    Ok((output, PreviewLine::Styled(spans)))
+}
+
+/// The JSON object of the `#!` header line of a scenario (or its YAML front
+/// matter).
+#[derive(Deserialize, Debug)]
+pub struct ScenarioHeader {
+    #[serde(default = "default_step")]
+    pub step: f64,
+
+    #[serde(default = "default_width")]
+    pub width: u32,
+
+    #[serde(default = "default_height")]
+    pub height: u32,
+
+    #[serde(default = "default_initial_offset")]
+    pub initial_offset: f64,
+
+    #[serde(default)]
+    pub start_delay: f64,
+
+    #[serde(default)]
+    pub log_colors: BTreeMap<String, String>,
+
+    #[serde(default = "default_log_timestamp")]
+    pub log_timestamp: bool,
+
+    #[serde(default)]
+    pub shell_indent: usize,
+
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+
+    #[serde(default)]
+    pub rprompt: Option<String>,
+
+    #[serde(default = "default_prompt_prefixes")]
+    pub prompt_prefixes: Vec<String>,
+
+    #[serde(default)]
+    pub title: Option<String>,
+
+    #[serde(default)]
+    pub env: Option<BTreeMap<String, String>>,
+
+    #[serde(default)]
+    pub idle_time_limit: Option<f64>,
+
+    #[serde(default)]
+    pub output_step: Option<f64>,
+
+    #[serde(default)]
+    pub emit_input: bool,
+
+    #[serde(default = "default_blank_pause")]
+    pub blank_pause: f64,
+
+    #[serde(default = "default_pause_gap")]
+    pub pause_gap: f64,
+
+    #[serde(default = "default_max_timeout")]
+    pub max_timeout: f64,
+
+    #[serde(default = "default_clear_pre_pause")]
+    pub clear_pre_pause: f64,
+
+    #[serde(default = "default_clear_post_pause")]
+    pub clear_post_pause: f64,
+
+    #[serde(default = "default_comment_sgr")]
+    pub comment_sgr: String,
+
+    #[serde(default = "default_prompt_sgr")]
+    pub prompt_sgr: String,
+
+    #[serde(default = "default_tabstop")]
+    pub tabstop: usize,
+
+    #[serde(default)]
+    pub manage_cursor: bool,
+
+    #[serde(default)]
+    pub newline: Newline,
+
+    #[serde(default)]
+    pub chain_pause: f64,
+
+    #[serde(default = "default_prompt_delay")]
+    pub prompt_delay: f64,
+
+    #[serde(default = "default_command_delay")]
+    pub command_delay: f64,
+
+    #[serde(default = "default_slow_factor")]
+    pub slow_factor: f64,
+
+    #[serde(default = "default_fast_factor")]
+    pub fast_factor: f64,
+
+    #[serde(default = "default_font_family")]
+    pub font_family: String,
+
+    #[serde(default = "default_font_size")]
+    pub font_size: u32,
+
+    #[serde(default = "default_theme")]
+    pub theme: String,
+
+    #[serde(default)]
+    pub preview_truncate: bool,
+
+    #[serde(default = "default_hint_duration")]
+    pub hint_duration: f64,
+}
+
+impl Default for ScenarioHeader {
+    fn default() -> Self {
+        from_str("{}").unwrap()
+    }
+}
+
+/// Whether the first line of a scenario is a shebang (eg.
+/// `#!/usr/bin/env asciinema-scenario`) making it executable, rather than a
+/// `#! {...}` header.
+fn is_shebang(line: &str) -> bool {
+    line.strip_prefix("#!")
+        .is_some_and(|path| path.trim_start().starts_with('/'))
+}
+
+/// Parse the first line of a scenario, a line not starting with `#! ` (or a
+/// shebang) isn't a header and gives the defaults.
+pub fn parse_header(line: &str) -> Result<ScenarioHeader, Error> {
+    match line.strip_prefix("#! ").filter(|_| !is_shebang(line)) {
+        Some(json) => from_str(json).map_err(|e| format_err!("invalid header `{}`: {}", json, e)),
+        None => Ok(ScenarioHeader::default()),
+    }
+}
+
+/// Parse the YAML front matter of a scenario, the lines between a first
+/// `---` line and the next one, it has the same fields as the `#!` header.
+pub fn parse_front_matter(yaml: &str) -> Result<ScenarioHeader, Error> {
+    // YAML reads an empty document as `null`
+    if yaml.trim().is_empty() {
+        return Ok(ScenarioHeader::default());
+    }
+    serde_yaml::from_str(yaml).map_err(|e| format_err!("invalid front matter: {}", e))
+}
+
+/// Parse the header at the start of a scenario, a `#!` line or a YAML front
+/// matter.
+pub fn read_header(input: impl BufRead) -> Result<ScenarioHeader, Error> {
+    Ok(take_header(&mut input.lines().enumerate().peekable())?.0)
+}
+
+/// Numbered lines of the input.
+type InputLines = Vec<(usize, io::Result<String>)>;
+
+/// Take the YAML front matter (after a shebang, if any) off the start of a
+/// scenario, or parse its first line as a `#!` header line which is left to
+/// skip. Also gives the lines taken which are not a front matter after all,
+/// to be read again.
+fn take_header<I>(lines: &mut Peekable<I>) -> Result<(ScenarioHeader, InputLines), Error>
+where
+    I: Iterator<Item = (usize, io::Result<String>)>,
+{
+    if let Some((_, Ok(line))) = lines.peek() {
+        if is_shebang(line) {
+            lines.next();
+        }
+    }
+    let yaml = match take_front_matter(lines) {
+        (Some(yaml), _) => yaml,
+        (None, taken) if !taken.is_empty() => return Ok((ScenarioHeader::default(), taken)),
+        (None, _) => {
+            let header = match lines.peek() {
+                Some((_, Ok(line))) => parse_header(line).map_err(at_line(1))?,
+                _ => ScenarioHeader::default(),
+            };
+            return Ok((header, vec![]));
+        }
+    };
+    if let Some((index, Ok(line))) = lines.peek() {
+        if line.starts_with("#! ") {
+            warn!(
+                "ignoring the `#!` header on line {}, the front matter is used",
+                index + 1
+            );
+        }
+    }
+    Ok((parse_front_matter(&yaml).map_err(at_line(1))?, vec![]))
+}
+
+/// Take a YAML front matter off the start of a scenario: a YAML mapping
+/// between two `---` lines. Otherwise the `---` line clears the display, the
+/// lines taken to find it out are given back to be read again.
+fn take_front_matter<I>(lines: &mut Peekable<I>) -> (Option<String>, InputLines)
+where
+    I: Iterator<Item = (usize, io::Result<String>)>,
+{
+    match lines.peek() {
+        Some((_, Ok(line))) if line.trim_end() == "---" => {}
+        _ => return (None, vec![]),
+    }
+    let mut taken: InputLines = lines.next().into_iter().collect();
+    let mut yaml = String::new();
+    for (index, line) in lines.by_ref() {
+        match line {
+            Ok(line) if line.trim_end() == "---" => {
+                match serde_yaml::from_str(&yaml) {
+                    Ok(serde_yaml::Value::Mapping(_)) | Ok(serde_yaml::Value::Null) => {
+                        return (Some(yaml), vec![])
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("line 1: `---` doesn't open a front matter: {}", e),
+                }
+                taken.push((index, Ok(line)));
+                break;
+            }
+            Ok(line) => {
+                yaml.push_str(&line);
+                yaml.push('\n');
+                taken.push((index, Ok(line)));
+            }
+            Err(e) => {
+                taken.push((index, Err(e)));
+                break;
+            }
+        }
+    }
+    (None, taken)
+}
+
+fn default_step() -> f64 {
+    0.10
+}
+
+fn default_width() -> u32 {
+    77
+}
+
+fn default_height() -> u32 {
+    20
+}
+
+fn default_initial_offset() -> f64 {
+    3.0
+}
+
+fn default_log_timestamp() -> bool {
+    true
+}
+
+fn default_blank_pause() -> f64 {
+    3.0
+}
+
+fn default_pause_gap() -> f64 {
+    30.0
+}
+
+fn default_max_timeout() -> f64 {
+    3600.0
+}
+
+fn default_prompt_delay() -> f64 {
+    3.0
+}
+
+fn default_command_delay() -> f64 {
+    3.0
+}
+
+fn default_slow_factor() -> f64 {
+    2.0
+}
+
+fn default_fast_factor() -> f64 {
+    0.5
+}
+
+fn default_clear_pre_pause() -> f64 {
+    18.0
+}
+
+fn default_clear_post_pause() -> f64 {
+    3.0
+}
+
+fn default_comment_sgr() -> String {
+    "1".to_string()
+}
+
+fn default_prompt_sgr() -> String {
+    "32".to_string()
+}
+
+fn default_tabstop() -> usize {
+    8
+}
+
+fn default_font_family() -> String {
+    "DejaVu Sans Mono, Menlo, Consolas, monospace".to_string()
+}
+
+fn default_font_size() -> u32 {
+    15
+}
+
+fn default_theme() -> String {
+    THEMES[0].name.to_string()
+}
+
+fn default_hint_duration() -> f64 {
+    3.0
+}
+
+fn default_prompt() -> String {
+    "$ ".to_string()
+}
+
+fn default_prompt_prefixes() -> Vec<String> {
+    vec!["(nix-shell) $ ".to_string()]
+}
+
+/// The header of an asciicast v2 recording. Same as the `Header` of the
+/// `asciicast` crate, with the environment sorted by name so that the same
+/// scenario always gives the same bytes, and the `scenario_hash` of
+/// `--embed-hash` as a custom field.
+#[derive(Serialize)]
+struct CastHeader<'a> {
+    version: u8,
+    width: u32,
+    height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_time_limit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: &'a Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: &'a Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenario_hash: &'a Option<String>,
+}
+
+fn print_header(
+    out: &mut dyn Write,
+    header: &ScenarioHeader,
+    duration: Option<f64>,
+    options: &OutputOptions,
+) -> Result<(), Error> {
+    let cast_header = CastHeader {
+        version: 2,
+        width: header.width,
+        height: header.height,
+        timestamp: options.timestamp,
+        duration,
+        idle_time_limit: header.idle_time_limit,
+        title: &header.title,
+        env: &header.env,
+        scenario_hash: &options.scenario_hash,
+    };
+    let line = to_json_line(&cast_header, options.pretty)?;
+    writeln!(out, "{}", line)?;
+    Ok(())
+}
+
+/// Timestamps are tracked as integer microseconds, so that adding up many
+/// small steps doesn't accumulate floating point errors.
+pub type Micros = u64;
+
+pub fn micros(seconds: f64) -> Micros {
+    (seconds * 1_000_000.0).round() as Micros
+}
+
+/// Parse a terminal size written as `<columns>x<rows>` (eg. `120x40`).
+fn parse_size(text: &str) -> Result<(u32, u32), Error> {
+    let text = text.trim();
+    text.split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| format_err!("invalid size `{}` (expected eg. `120x40`)", text))
+}
+
+/// An output event showing (or hiding) the cursor.
+fn cursor_entry(time: Micros, shown: bool) -> Entry {
+    Entry {
+        time: seconds(time),
+        event_type: EventType::Output,
+        event_data: if shown { "\x1b[?25h" } else { "\x1b[?25l" }.to_string(),
+    }
+}
+
+/// A resize event of the terminal to `size` (`<columns>x<rows>`).
+fn resize_entry(time: Micros, (width, height): (u32, u32)) -> Entry {
+    Entry {
+        time: seconds(time),
+        event_type: EventType::Resize,
+        event_data: format!("{}x{}", width, height),
+    }
+}
+
+/// Prefix an error with the (1-based) number of the line of the scenario it
+/// comes from.
+fn at_line(line: impl Display) -> impl Fn(Error) -> Error {
+    move |e| format_err!("line {}: {}", line, e)
+}
+
+/// Where a line of the scenario comes from: its (1-based) number and, for
+/// the lines of an `#include:`d scenario, the file and where it is included,
+/// shown as eg. "2 of `setup.scenario`, included on line 5".
+#[derive(Clone, Debug)]
+struct Location {
+    line: usize,
+    include: Option<Rc<(String, Location)>>,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.line)?;
+        if let Some(include) = &self.include {
+            write!(f, " of `{}`, included on line {}", include.0, include.1)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn seconds(time: Micros) -> f64 {
+    time as f64 / 1_000_000.0
+}
+
+/// Parse a variable definition written as `<NAME>=<value>` (eg.
+/// `VERSION=1.2`), the name is made of letters, digits and `_`.
+pub fn parse_variable(text: &str) -> Result<(String, String), Error> {
+    let text = text.trim_start();
+    text.split_once('=')
+        .filter(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format_err!("invalid variable `{}` (expected eg. `VERSION=1.2`)", text))
+}
+
+/// Replace the `${NAME}` variables of `line` by their value. An undefined
+/// variable is an error with `strict`, otherwise it is kept as is.
+fn substitute(
+    line: &str,
+    variables: &HashMap<String, String>,
+    strict: bool,
+    location: &Location,
+) -> Result<String, Error> {
+    let mut substituted = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        substituted.push_str(&rest[..start]);
+        match variables.get(name) {
+            Some(value) => substituted.push_str(value),
+            None if strict => {
+                return Err(format_err!(
+                    "line {}: undefined variable `{}`",
+                    location,
+                    name
+                ))
+            }
+            None => {
+                warn!("undefined variable `{}` on line {}", name, location);
+                substituted.push_str(&rest[start..=end]);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+/// Parse a duration in seconds, either a plain number of seconds or a number
+/// with a `ms`, `s` or `m` suffix (eg. `500ms`, `2s`, `1m`).
+pub fn parse_duration(text: &str) -> Result<f64, Error> {
+    let text = text.trim();
+    let (number, unit) = [("ms", 0.001), ("s", 1.0), ("m", 60.0)]
+        .iter()
+        .find_map(|(suffix, unit)| text.strip_suffix(suffix).map(|number| (number, *unit)))
+        .unwrap_or((text, 1.0));
+    let duration = number
+        .trim_end()
+        .parse()
+        .ok()
+        .map(|number: f64| number * unit)
+        .filter(|duration| duration.is_finite())
+        .ok_or_else(|| {
+            format_err!(
+                "invalid duration `{}` (expected eg. `2.5`, `500ms`, `2s` or `1m`)",
+                text
+            )
+        })?;
+    if duration < 0.0 {
+        return Err(format_err!("duration `{}` must not be negative", text));
+    }
+    Ok(duration)
+}
+
+/// Precision of the timestamps in the generated cast.
+#[derive(Clone, Copy, Debug)]
+pub enum TimeUnits {
+    /// seconds rounded to two decimals
+    Seconds,
+    /// exact microseconds
+    Microseconds,
+}
+
+impl FromStr for TimeUnits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s" => Ok(TimeUnits::Seconds),
+            "us" => Ok(TimeUnits::Microseconds),
+            _ => Err(format_err!("unknown time units `{}` (expected s or us)", s)),
+        }
+    }
+}
+
+/// How the lines printed in the cast end, see `newline` in the header.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Newline {
+    /// `\r\n`, like a real terminal
+    #[default]
+    Crlf,
+    /// `\n`, for consumers of the cast taking a `\r` literally
+    Lf,
+}
+
+impl Newline {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Newline::Crlf => "\r\n",
+            Newline::Lf => "\n",
+        }
+    }
+}
+
+/// Format of the generated cast.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CastFormat {
+    /// a single JSON object with the printed data and the delays between them
+    V1,
+    /// a header line followed by one line per event
+    V2,
+}
+
+impl FromStr for CastFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(CastFormat::V1),
+            "v2" => Ok(CastFormat::V2),
+            _ => Err(format_err!(
+                "unknown cast format `{}` (expected v1 or v2)",
+                s
+            )),
+        }
+    }
+}
+
+/// Options controlling how the cast is written out.
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    pub format: CastFormat,
+    pub time_units: TimeUnits,
+    /// written into the header when set
+    pub scenario_hash: Option<String>,
+    /// Unix timestamp of the cast, written into the header when set
+    pub timestamp: Option<i64>,
+    /// write a space after every `,` and `:` of the JSON lines
+    pub pretty: bool,
+}
+
+/// JSON formatter writing a space after every `,` and `:`, everything stays
+/// on one line since asciicast needs every event on its own line.
+struct SpacedFormatter;
+
+impl Formatter for SpacedFormatter {
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+/// Serialize `value` into a line of JSON, spaced out with `pretty`.
+fn to_json_line(value: &impl Serialize, pretty: bool) -> Result<String, Error> {
+    if !pretty {
+        return Ok(to_string(value)?);
+    }
+    let mut json = vec![];
+    value.serialize(&mut Serializer::with_formatter(&mut json, SpacedFormatter))?;
+    Ok(String::from_utf8(json)?)
+}
+
+/// Round `time` to the precision of the cast.
+fn output_time(time: f64, options: &OutputOptions) -> f64 {
+    match options.time_units {
+        TimeUnits::Seconds => format!("{:.2}", time).parse().unwrap(),
+        TimeUnits::Microseconds => seconds(micros(time)),
+    }
+}
+
+/// Round the times of `entries` to the precision of the cast. Events at
+/// different times are kept at least one unit (eg. 0.01 s) apart, so that
+/// rounding never gives them the same time or puts them out of order.
+fn output_times(entries: &[Entry], options: &OutputOptions) -> Vec<f64> {
+    let mut last = None;
+    entries
+        .iter()
+        .map(|entry| next_output_time(&mut last, entry.time, options))
+        .collect()
+}
+
+/// Round `time` following the `last` time and its rounded time, see
+/// `output_times`.
+fn next_output_time(last: &mut Option<(f64, f64)>, time: f64, options: &OutputOptions) -> f64 {
+    let unit = match options.time_units {
+        TimeUnits::Seconds => 0.01,
+        TimeUnits::Microseconds => 0.000_001,
+    };
+    let mut output = output_time(time, options);
+    match *last {
+        Some((last_time, last_output)) if time > last_time => {
+            output = output.max(output_time(last_output + unit, options));
+        }
+        Some((last_time, last_output)) if time == last_time => output = last_output,
+        // an earlier time (eg. of an `#entry:` line) is kept as is
+        _ => {}
+    }
+    *last = Some((time, output));
+    output
+}
+
+pub fn print_entry(
+    out: &mut dyn Write,
+    entry: &Entry,
+    options: &OutputOptions,
+) -> Result<(), Error> {
+    let t = output_time(entry.time, options);
+    writeln!(
+        out,
+        "{}",
+        to_json_line(
+            &Entry {
+                time: t,
+                event_type: entry.event_type,
+                event_data: entry.event_data.clone(),
+            },
+            options.pretty
+        )?
+    )?;
+    Ok(())
+}
+
+/// An asciicast v1 recording.
+#[derive(Serialize)]
+struct CastV1<'a> {
+    version: u8,
+    width: u32,
+    height: u32,
+    duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: &'a Option<String>,
+    env: BTreeMap<String, String>,
+    /// the printed data, each with the delay since the previous one
+    stdout: Vec<(f64, &'a str)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scenario_hash: &'a Option<String>,
+}
+
+/// Write the output events of the cast as an asciicast v1 recording, the
+/// other events (eg. markers) have no equivalent.
+fn print_cast_v1(
+    out: &mut dyn Write,
+    header: &ScenarioHeader,
+    entries: &[Entry],
+    times: &[f64],
+    options: &OutputOptions,
+) -> Result<(), Error> {
+    let mut previous = 0.0;
+    let mut stdout = vec![];
+    for (entry, time) in entries.iter().zip(times) {
+        if entry.event_type == EventType::Output {
+            stdout.push((
+                output_time((time - previous).max(0.0), options),
+                entry.event_data.as_str(),
+            ));
+            previous = *time;
+        }
+    }
+    let cast = CastV1 {
+        version: 1,
+        width: header.width,
+        height: header.height,
+        duration: previous,
+        title: &header.title,
+        env: header.env.clone().unwrap_or_default(),
+        stdout,
+        scenario_hash: &options.scenario_hash,
+    };
+    writeln!(out, "{}", to_json_line(&cast, options.pretty)?)?;
+    Ok(())
+}
+
+pub fn print_cast(
+    out: &mut dyn Write,
+    header: &ScenarioHeader,
+    entries: &[Entry],
+    options: &OutputOptions,
+) -> Result<(), Error> {
+    let times = output_times(entries, options);
+    if options.format == CastFormat::V1 {
+        return print_cast_v1(out, header, entries, &times, options);
+    }
+    // the duration is the time of the last event
+    let duration = times.iter().fold(None, |duration: Option<f64>, time| {
+        Some(duration.map_or(*time, |duration| duration.max(*time)))
+    });
+    print_header(out, header, duration, options)?;
+    for (entry, time) in entries.iter().zip(times) {
+        print_entry(
+            out,
+            &Entry {
+                time,
+                ..entry.clone()
+            },
+            options,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes an asciicast v2 recording one entry at a time, rounding the times
+/// like [`print_cast`], eg. while the scenario is rendered (see [`stream`]).
+/// The header is written first, so it has no `duration`.
+pub struct CastWriter<'a> {
+    out: &'a mut dyn Write,
+    options: &'a OutputOptions,
+    /// the time of the last entry and its rounded time
+    last: Option<(f64, f64)>,
+}
+
+impl<'a> CastWriter<'a> {
+    pub fn new(
+        out: &'a mut dyn Write,
+        header: &ScenarioHeader,
+        options: &'a OutputOptions,
+    ) -> Result<Self, Error> {
+        if options.format == CastFormat::V1 {
+            return Err(format_err!(
+                "asciicast v1 casts can't be written one entry at a time"
+            ));
+        }
+        print_header(out, header, None, options)?;
+        Ok(CastWriter {
+            out,
+            options,
+            last: None,
+        })
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> Result<(), Error> {
+        let time = next_output_time(&mut self.last, entry.time, self.options);
+        print_entry(
+            self.out,
+            &Entry {
+                time,
+                ..entry.clone()
+            },
+            self.options,
+        )
+    }
+}
+
+/// Check that `cast` is a valid asciicast v2 recording: a header with
+/// `version` 2 and the size of the terminal, then one `[time, code, data]`
+/// event per line with a known code and times that never go back.
+pub fn validate_cast(cast: &str) -> Result<(), Error> {
+    let mut lines = cast.lines().enumerate();
+    let header: serde_json::Value = match lines.next() {
+        Some((_, line)) => {
+            from_str(line).map_err(|e| format_err!("line 1: invalid header: {}", e))?
+        }
+        None => return Err(format_err!("the cast is empty")),
+    };
+    if header["version"] != 2 {
+        return Err(format_err!(
+            "line 1: the version is {} instead of 2",
+            header["version"]
+        ));
+    }
+    if header["width"].as_u64().is_none() || header["height"].as_u64().is_none() {
+        return Err(format_err!(
+            "line 1: the header has no `width` and `height`"
+        ));
+    }
+
+    let mut previous = 0.0;
+    for (index, line) in lines {
+        let entry: Entry = from_str(line)
+            .map_err(|e| format_err!("line {}: invalid event `{}`: {}", index + 1, line, e))?;
+        if !entry.time.is_finite() || entry.time < 0.0 {
+            return Err(format_err!(
+                "line {}: invalid time {}",
+                index + 1,
+                entry.time
+            ));
+        }
+        if entry.time < previous {
+            return Err(format_err!(
+                "line {}: time {} is before the time {} of the previous event",
+                index + 1,
+                entry.time,
+                previous
+            ));
+        }
+        previous = entry.time;
+    }
+    Ok(())
+}
+
+/// Parse a single asciicast entry (eg. `[1.5, "o", "hello"]`).
+pub fn parse_entry(line: &str) -> Result<Entry, Error> {
+    let entry: Entry = from_str(line)
+        .map_err(|e| format_err!("invalid asciicast entry `{}`: {}", line.trim(), e))?;
+    if !entry.time.is_finite() || entry.time < 0.0 {
+        return Err(format_err!("invalid entry time `{}`", entry.time));
+    }
+    Ok(entry)
+}
+
+/// Split the argument of an `#embed:` line into the path of the cast and
+/// whether the terminal is resized to the size of its header (a trailing
+/// `resize`).
+fn embed_arguments(text: &str) -> (&str, bool) {
+    let text = text.trim();
+    match text.strip_suffix("resize") {
+        Some(path) if path.ends_with(char::is_whitespace) => (path.trim_end(), true),
+        _ => (text, false),
+    }
+}
+
+/// Read the terminal size and the events of the asciicast v2 recording at
+/// `path`, for `#embed:` lines.
+fn read_cast(path: &Path) -> Result<((u32, u32), Vec<Entry>), Error> {
+    let file =
+        File::open(path).map_err(|e| format_err!("can not embed `{}`: {}", path.display(), e))?;
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let header: serde_json::Value = match lines.next() {
+        Some((_, line)) => from_str(&line?)
+            .map_err(|e| format_err!("{}:1: invalid header: {}", path.display(), e))?,
+        None => return Err(format_err!("`{}` is empty", path.display())),
+    };
+    if header["version"] != 2 {
+        return Err(format_err!(
+            "{}:1: only asciicast v2 recordings can be embedded, the version is {}",
+            path.display(),
+            header["version"]
+        ));
+    }
+    let size = match (header["width"].as_u64(), header["height"].as_u64()) {
+        (Some(width), Some(height)) => (width as u32, height as u32),
+        _ => {
+            return Err(format_err!(
+                "{}:1: the header has no `width` and `height`",
+                path.display()
+            ))
+        }
+    };
+
+    let mut entries: Vec<Entry> = vec![];
+    for (index, maybe_line) in lines {
+        let line = maybe_line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_entry(&line)
+            .map_err(|e| format_err!("{}:{}: {}", path.display(), index + 1, e))?;
+        if let Some(previous) = entries.last().filter(|previous| entry.time < previous.time) {
+            return Err(format_err!(
+                "{}:{}: time {} is before the time {} of the previous event",
+                path.display(),
+                index + 1,
+                entry.time,
+                previous.time
+            ));
+        }
+        entries.push(entry);
+    }
+    Ok((size, entries))
+}
+
+/// Read additional entries from a newline delimited JSON file and merge them
+/// by time into the generated ones.
+pub fn merge_entries(entries: &mut Vec<Entry>, path: &str) -> Result<(), Error> {
+    let reader = BufReader::new(File::open(path)?);
+    for (index, maybe_line) in reader.lines().enumerate() {
+        let line = maybe_line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_entry(&line).map_err(|e| format_err!("{}:{}: {}", path, index + 1, e))?;
+        let time = format!("{:.2}", entry.time);
+        if entries.iter().any(|e| format!("{:.2}", e.time) == time) {
+            warn!(
+                "{}:{}: entry collides with a generated entry at {}s",
+                path,
+                index + 1,
+                time
+            );
+        }
+        entries.push(entry);
+    }
+    normalize(entries);
+    Ok(())
+}
+
+/// Order entries by time. Sorting is stable so entries sharing a timestamp
+/// keep their order, ie. generated entries come before merged ones.
+fn normalize(entries: &mut [Entry]) {
+    entries.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+}
+
+/// Remove the trailing newline of the last output entry, dropping the entry
+/// when nothing else is left of it. Entries after it which only hold escape
+/// sequences (eg. showing the cursor again with `manage_cursor`), markers
+/// and resizes are skipped.
+pub fn strip_final_newline(entries: &mut Vec<Entry>) {
+    let last = entries.iter().rposition(|entry| {
+        entry.event_type == EventType::Output && !strip_ansi(&entry.event_data).is_empty()
+    });
+    if let Some(index) = last {
+        let data = &entries[index].event_data;
+        let stripped = data
+            .strip_suffix("\r\n")
+            .or_else(|| data.strip_suffix('\n'));
+        if let Some(stripped) = stripped {
+            if stripped.is_empty() {
+                entries.remove(index);
+            } else {
+                entries[index].event_data = stripped.to_string();
+            }
+        }
+    }
+}
+
+/// A part of the scenario between two clears, see [`RenderOptions::split`].
+#[derive(Debug)]
+pub struct Clip {
+    /// label of the `#chapter:` starting the clip
+    pub label: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// Writes each clip into its own cast file in a directory, for
+/// `--split-on-clear`.
+pub struct Clips {
+    options: OutputOptions,
+    dir: PathBuf,
+    count: usize,
+}
+
+impl Clips {
+    pub fn new(dir: &str, options: OutputOptions) -> Result<Self, Error> {
+        create_dir_all(dir)?;
+        Ok(Clips {
+            options,
+            dir: PathBuf::from(dir),
+            count: 0,
+        })
+    }
+
+    /// Name of the next clip, eg. `chapter_01.cast` or
+    /// `chapter_02_getting-started.cast` when the chapter has a label.
+    fn filename(&self, label: &Option<String>) -> PathBuf {
+        let mut name = format!("chapter_{:02}", self.count + 1);
+        if let Some(label) = label {
+            let slug: Vec<String> = label
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|part| !part.is_empty())
+                .map(|part| part.to_lowercase())
+                .collect();
+            if !slug.is_empty() {
+                name = format!("{}_{}", name, slug.join("-"));
+            }
+        }
+        self.dir.join(format!("{}.cast", name))
+    }
+
+    /// Write out the next clip.
+    pub fn write(&mut self, header: &ScenarioHeader, clip: &Clip) -> Result<(), Error> {
+        let filename = self.filename(&clip.label);
+        if filename.exists() {
+            return Err(format_err!(
+                "cast file `{}` already exist!",
+                filename.display()
+            ));
+        }
+        print_cast(
+            &mut File::create(&filename)?,
+            header,
+            &clip.entries,
+            &self.options,
+        )?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Finish the current clip (if it has any entries) and start a new one
+/// labelled `label`.
+fn split(
+    clips: &mut Vec<Clip>,
+    current: &mut Option<String>,
+    entries: &mut Vec<Entry>,
+    label: Option<String>,
+) {
+    if !entries.is_empty() {
+        clips.push(Clip {
+            label: current.take(),
+            entries: std::mem::take(entries),
+        });
+        *current = label;
+    } else if label.is_some() {
+        *current = label;
+    }
+}
+
+/// A typing profile models the delay between two keystrokes of a typist.
+#[derive(Clone, Copy, Debug)]
+struct TypingProfile {
+    /// base delay between two keystrokes (replaces `step`)
+    step: f64,
+    /// maximum relative deviation from `step` for a single keystroke
+    spread: f64,
+    /// delay multiplier for the first keystroke after finishing a word
+    word_pause: f64,
+    /// delay multiplier for the second character of a common digraph
+    digraph: f64,
+}
+
+const TYPING_PROFILES: &[(&str, TypingProfile)] = &[
+    (
+        "fast",
+        TypingProfile {
+            step: 0.05,
+            spread: 0.3,
+            word_pause: 2.0,
+            digraph: 0.6,
+        },
+    ),
+    (
+        "average",
+        TypingProfile {
+            step: 0.10,
+            spread: 0.4,
+            word_pause: 2.5,
+            digraph: 0.75,
+        },
+    ),
+    (
+        "hunt-peck",
+        TypingProfile {
+            step: 0.25,
+            spread: 0.6,
+            word_pause: 3.0,
+            digraph: 0.9,
+        },
+    ),
+];
+
+const COMMON_DIGRAPHS: &[&str] = &[
+    "th", "he", "in", "er", "an", "re", "on", "at", "en", "nd", "ti", "es", "or", "te", "of", "ed",
+    "is", "it", "al", "ar", "st", "to", "nt", "ng", "se", "ha", "as", "ou", "io", "le", "ls", "cd",
+    "gi", "--",
+];
+
+impl TypingProfile {
+    fn from_name(name: &str) -> Result<Self, Error> {
+        TYPING_PROFILES
+            .iter()
+            .find(|(profile_name, _)| *profile_name == name)
+            .map(|(_, profile)| *profile)
+            .ok_or_else(|| {
+                let names: Vec<&str> = TYPING_PROFILES.iter().map(|(name, _)| *name).collect();
+                format_err!(
+                    "unknown typing profile `{}` (expected one of: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Decides how long each keystroke takes: `step` with a random `jitter`, or
+/// drawn from a typing profile using a (seedable) random number generator.
+struct Typing {
+    profile: Option<TypingProfile>,
+    /// maximum relative deviation from `step` without a typing profile
+    jitter: f64,
+    rng: fastrand::Rng,
+    /// write an input event before the output of every keystroke
+    emit_input: bool,
+    /// SGR parameters of typed comments
+    comment_sgr: String,
+    /// what ends a typed command, see `newline` in the header
+    newline: Newline,
+    /// pause before and after chain operators, in steps
+    chain_pause: f64,
+    /// pause between the prompt and the first keystroke, in steps
+    prompt_delay: f64,
+    /// pause between the last keystroke and the newline, in steps
+    command_delay: f64,
+    /// multiplier of the delay of every keystroke, set by `#typing:`
+    factor: f64,
+    /// longest `{{pause:}}`, see `max_timeout` in the header
+    max_timeout: f64,
+}
+
+impl Typing {
+    /// Type as set in the `header`.
+    fn new(header: &ScenarioHeader, seed: Option<u64>, jitter: f64) -> Self {
+        Typing {
+            profile: None,
+            jitter,
+            emit_input: header.emit_input,
+            comment_sgr: header.comment_sgr.clone(),
+            newline: header.newline,
+            chain_pause: header.chain_pause,
+            prompt_delay: header.prompt_delay,
+            command_delay: header.command_delay,
+            factor: 1.0,
+            max_timeout: header.max_timeout,
+            rng: match seed {
+                Some(seed) => fastrand::Rng::with_seed(seed),
+                None => fastrand::Rng::new(),
+            },
+        }
+    }
+
+    fn delay(&mut self, step: f64, previous: Option<char>, current: char) -> f64 {
+        let profile = match self.profile {
+            Some(profile) => profile,
+            None if self.jitter > 0.0 => {
+                return step * (1.0 + self.jitter * (2.0 * self.rng.f64() - 1.0));
+            }
+            None => return step,
+        };
+        let mut delay = step * (1.0 + profile.spread * (2.0 * self.rng.f64() - 1.0));
+        match previous {
+            Some(previous) if previous.is_whitespace() && !current.is_whitespace() => {
+                delay *= profile.word_pause;
+            }
+            Some(previous) => {
+                let digraph: String = [previous, current].iter().collect();
+                if COMMON_DIGRAPHS.contains(&digraph.to_lowercase().as_str()) {
+                    delay *= profile.digraph;
+                }
+            }
+            None => {}
+        }
+        delay
+    }
+}
+
+/// Cursor shapes of `#cursor-shape:` lines, with the DECSCUSR parameter of
+/// their blinking variant (the steady one is the next number).
+const CURSOR_SHAPES: &[(&str, u8)] = &[("block", 1), ("underline", 3), ("bar", 5)];
+
+/// DECSCUSR escape sequence for a `#cursor-shape:` line (eg. `bar blink`).
+fn cursor_shape(arguments: &str) -> Result<String, Error> {
+    let mut words = arguments.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let blinking = CURSOR_SHAPES
+        .iter()
+        .find(|(shape, _)| *shape == name)
+        .map(|(_, parameter)| *parameter)
+        .ok_or_else(|| {
+            format_err!(
+                "unknown cursor shape `{}` (expected one of: {})",
+                name,
+                CURSOR_SHAPES
+                    .iter()
+                    .map(|(shape, _)| *shape)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+    let parameter = match (words.next(), words.next()) {
+        (None, None) | (Some("steady"), None) => blinking + 1,
+        (Some("blink"), None) => blinking,
+        _ => {
+            return Err(format_err!(
+                "unexpected `{}` after the cursor shape (expected `blink` or `steady`)",
+                arguments.trim()[name.len()..].trim()
+            ))
+        }
+    };
+    Ok(format!("\x1b[{} q", parameter))
+}
+
+/// Scale `time` to the `speed` of the cast, see [`RenderOptions::speed`].
+fn scaled(time: Micros, speed: f64) -> Micros {
+    (time as f64 / speed).round() as Micros
+}
+
+/// Shorten every pause between two entries to at most `limit`, like players
+/// do with `idle_time_limit`. Returns the original and the new time of every
+/// entry, see `moved`.
+fn limit_idle_time(entries: &mut [Entry], limit: Micros) -> Vec<(Micros, Micros)> {
+    let mut moves: Vec<(Micros, Micros)> = vec![];
+    for entry in entries.iter_mut() {
+        let original = micros(entry.time);
+        let new = match moves.last() {
+            Some((previous_original, previous_new)) => {
+                previous_new + original.saturating_sub(*previous_original).min(limit)
+            }
+            None => original,
+        };
+        entry.time = seconds(new);
+        moves.push((original, new));
+    }
+    moves
+}
+
+/// Where `time` ends up after `limit_idle_time`, `moves` are ordered by
+/// their original time (but for entries of `#entry:` lines in the past).
+fn moved(moves: &[(Micros, Micros)], limit: Micros, time: Micros) -> Micros {
+    match moves.partition_point(|(original, _)| *original <= time) {
+        0 => time,
+        index => {
+            let (original, new) = moves[index - 1];
+            new + time.saturating_sub(original).min(limit)
+        }
+    }
+}
+
+fn clear_terminal(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: &f64,
+    header: &ScenarioHeader,
+) -> Result<(), Error> {
+    *time = time.saturating_add(micros(header.clear_pre_pause * step));
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: "\r\x1b[2J\r\x1b[H".to_string(),
+    });
+    *time = time.saturating_add(micros(header.clear_post_pause * step));
+    Ok(())
+}
+
+/// A keystroke of a typed command.
+enum Keystroke {
+    /// a grapheme cluster, eg. a letter with its accents or a ZWJ emoji
+    /// sequence, typed at once
+    Grapheme(String),
+    /// a grapheme typed as is, written as `\#`
+    Literal(String),
+    /// erase the last typed graphemes, written as `{{bs:N}}`
+    Backspace(usize),
+    /// wait before typing on, in seconds, written as `{{pause:DURATION}}`
+    Pause(f64),
+    /// an escape sequence (eg. a color), printed without a delay
+    Escape(String),
+}
+
+/// Number of terminal cells a grapheme cluster takes, wide characters (eg.
+/// CJK or emoji) take two.
+fn cells(grapheme: &str) -> usize {
+    grapheme.width().clamp(1, 2)
+}
+
+/// Number of terminal cells a grapheme cluster takes at `column`, a tab
+/// moves to the next multiple of `tabstop`.
+fn cells_at(grapheme: &str, column: usize, tabstop: usize) -> usize {
+    match grapheme {
+        "\t" => tabstop - column % tabstop,
+        grapheme => cells(grapheme),
+    }
+}
+
+/// Length of the escape sequence `text` starts with, a CSI sequence (eg. the
+/// SGR sequence `\x1b[31m`) or an escape followed by one character.
+fn escape_sequence(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('\x1b')?;
+    match rest.strip_prefix('[') {
+        Some(params) => Some(
+            params
+                .find(|char| ('\x40'..='\x7e').contains(&char))
+                .map_or(text.len(), |end| 3 + end),
+        ),
+        None => Some(1 + rest.chars().next().map_or(0, char::len_utf8)),
+    }
+}
+
+/// `text` without its escape sequences, see `--strip-ansi`.
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = &rest[escape_sequence(rest).unwrap_or(1)..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Number of terminal cells `text` takes, tabs move to the next multiple of
+/// `tabstop`. Escape sequences take none.
+fn columns(text: &str, tabstop: usize) -> usize {
+    strip_ansi(text)
+        .graphemes(true)
+        .fold(0, |column, grapheme| {
+            column + cells_at(grapheme, column, tabstop)
+        })
+}
+
+/// Split the `\c` marker (as with `echo`) off the end of a line, the line is
+/// then not ended by a newline and the next one continues on the same row.
+fn strip_newline_marker(line: &str) -> (&str, bool) {
+    match line.strip_suffix("\\c") {
+        Some(line) => (line, false),
+        None => (line, true),
+    }
+}
+
+/// Split a command into keystrokes, `\{{` types a literal `{{` and `\#` a `#`
+/// that does not start a comment.
+fn keystrokes(line: &str) -> Result<Vec<Keystroke>, Error> {
+    let mut keystrokes = vec![];
+    let mut rest = line;
+    while let Some(grapheme) = rest.graphemes(true).next() {
+        if let Some(length) = escape_sequence(rest) {
+            keystrokes.push(Keystroke::Escape(rest[..length].to_string()));
+            rest = &rest[length..];
+        } else if let Some(stripped) = rest.strip_prefix("\\#") {
+            keystrokes.push(Keystroke::Literal("#".to_string()));
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("\\{{") {
+            keystrokes.push(Keystroke::Grapheme("{".to_string()));
+            keystrokes.push(Keystroke::Grapheme("{".to_string()));
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("{{bs:") {
+            let end = stripped
+                .find("}}")
+                .ok_or_else(|| format_err!("unterminated `{{{{bs:` in `{}`", line))?;
+            let count = stripped[..end].trim().parse().map_err(|_| {
+                format_err!("invalid `{{{{bs:{}}}}}` in `{}`", &stripped[..end], line)
+            })?;
+            keystrokes.push(Keystroke::Backspace(count));
+            rest = &stripped[end + 2..];
+        } else if let Some(stripped) = rest.strip_prefix("{{pause:") {
+            let end = stripped
+                .find("}}")
+                .ok_or_else(|| format_err!("unterminated `{{{{pause:` in `{}`", line))?;
+            let duration = parse_duration(&stripped[..end]).map_err(|_| {
+                format_err!("invalid `{{{{pause:{}}}}}` in `{}`", &stripped[..end], line)
+            })?;
+            keystrokes.push(Keystroke::Pause(duration));
+            rest = &stripped[end + 2..];
+        } else {
+            keystrokes.push(Keystroke::Grapheme(grapheme.to_string()));
+            rest = &rest[grapheme.len()..];
+        }
+    }
+    Ok(keystrokes)
+}
+
+fn echo_typing(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: &f64,
+    typing: &mut Typing,
+    line_raw: &str,
+) -> Result<(String, Option<usize>), Error> {
+    let (line_raw, newline) = strip_newline_marker(line_raw);
+    let mut bright_applied = false;
+    let mut comment = None;
+    let mut previous = None;
+    let mut chain = false;
+    let mut visible = String::new();
+    for keystroke in keystrokes(line_raw)? {
+        let (grapheme, literal) = match keystroke {
+            Keystroke::Grapheme(grapheme) => (grapheme, false),
+            Keystroke::Literal(grapheme) => (grapheme, true),
+            Keystroke::Backspace(count) => {
+                for _ in 0..count {
+                    // a wide character takes two cells to erase
+                    let cells = match visible.grapheme_indices(true).next_back() {
+                        Some((index, grapheme)) => {
+                            let cells = cells(grapheme);
+                            visible.truncate(index);
+                            cells
+                        }
+                        None => break,
+                    };
+                    *time = time.saturating_add(micros(typing.factor * *step));
+                    if typing.emit_input {
+                        entries.push(Entry {
+                            time: seconds(*time),
+                            event_type: EventType::Input,
+                            event_data: "\x7f".to_string(),
+                        });
+                    }
+                    entries.push(Entry {
+                        time: seconds(*time),
+                        event_type: EventType::Output,
+                        event_data: format!(
+                            "{}{}{}",
+                            "\x08".repeat(cells),
+                            " ".repeat(cells),
+                            "\x08".repeat(cells)
+                        ),
+                    });
+                }
+                // the terminal stays bold, what is typed next is still a comment
+                comment = comment.map(|comment: usize| comment.min(visible.len()));
+                previous = visible.chars().last();
+                continue;
+            }
+            Keystroke::Pause(duration) => {
+                check_timeout(typing.max_timeout, "{{pause:}}", duration)?;
+                *time = time.saturating_add(micros(duration));
+                continue;
+            }
+            Keystroke::Escape(sequence) => {
+                visible.push_str(&sequence);
+                entries.push(Entry {
+                    time: seconds(*time),
+                    event_type: EventType::Output,
+                    event_data: sequence,
+                });
+                continue;
+            }
+        };
+        // one delay per cell, wide characters (eg. CJK) take two
+        let first = grapheme.chars().next().unwrap_or_default();
+        *time = time.saturating_add(micros(
+            typing.factor * typing.delay(*step, previous, first) * cells(&grapheme) as f64,
+        ));
+        // chain operators (`&&`, `||`, `;` and `|`) are surrounded by a pause,
+        // `&` and `|` only when they start a word (eg. not in `2>&1`)
+        let operator = !literal && "&|;".contains(first);
+        if typing.chain_pause > 0.0
+            && operator != chain
+            && (chain || first == ';' || previous.is_none_or(char::is_whitespace))
+        {
+            *time = time.saturating_add(micros(typing.chain_pause * *step));
+            chain = operator;
+        }
+        previous = grapheme.chars().last();
+        if typing.emit_input {
+            entries.push(Entry {
+                time: seconds(*time),
+                event_type: EventType::Input,
+                event_data: grapheme.clone(),
+            });
+        }
+        if grapheme == "#" && !literal {
+            comment = comment.or(Some(visible.len()));
+            entries.push(Entry {
+                time: seconds(*time),
+                event_type: EventType::Output,
+                event_data: format!("\x1b[{}m", typing.comment_sgr),
+            });
+            bright_applied = true;
+        }
+        visible.push_str(&grapheme);
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: grapheme,
+        });
+    }
+    // clear
+    if bright_applied {
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: "\x1b[0m".to_string(),
+        });
+    }
+
+    *time = time.saturating_add(micros(typing.command_delay * step));
+    if !newline {
+        return Ok((visible, comment));
+    }
+    if typing.emit_input {
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Input,
+            event_data: "\r".to_string(),
+        });
+    }
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: typing.newline.as_str().to_string(),
+    });
+
+    Ok((visible, comment))
+}
+
+fn echo_console_line(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: &f64,
+    typing: &mut Typing,
+    header: &ScenarioHeader,
+    prefix: &str,
+    line: &str,
+) -> Result<PreviewLine, Error> {
+    *time = time.saturating_add(micros(*step));
+
+    let prompt = &header.prompt;
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: prompt_line(header, prefix, prompt, &header.rprompt),
+    });
+
+    *time = time.saturating_add(micros(typing.prompt_delay * step));
+
+    let (command, comment) = echo_typing(entries, time, step, typing, line)?;
+
+    Ok(PreviewLine::Console {
+        prefix: prefix.to_string(),
+        prompt: prompt.to_string(),
+        command,
+        comment,
+        rprompt: header.rprompt.clone(),
+    }
+    .terminated(strip_newline_marker(line).1))
+}
+
+/// The prompt printed before a command, with its prefix (eg. `(nix-shell) `)
+/// and its right prompt.
+fn prompt_line(
+    header: &ScenarioHeader,
+    prefix: &str,
+    prompt: &str,
+    rprompt: &Option<String>,
+) -> String {
+    let mut prompt_line: String = if !prefix.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m{}", header.prompt_sgr, prefix, prompt)
+    } else {
+        prompt.to_string()
+    };
+    // the right prompt is printed at the end of the row (whatever the width
+    // of the terminal) and the cursor moves back after the prompt
+    if let Some(rprompt) = rprompt {
+        let rprompt_width = PreviewLine::Styled(sgr_spans(rprompt)).width(header.tabstop);
+        prompt_line.push_str("\x1b7\x1b[999C");
+        if rprompt_width > 1 {
+            prompt_line.push_str(&format!("\x1b[{}D", rprompt_width - 1));
+        }
+        prompt_line.push_str(&format!("{}\x1b8", rprompt));
+    }
+    prompt_line
+}
+
+/// Print a command at once after the prompt, as if pasted (`$! ` lines).
+fn echo_pasted_line(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: &f64,
+    header: &ScenarioHeader,
+    prompt: &str,
+    rprompt: &Option<String>,
+    line: &str,
+) -> Result<PreviewLine, Error> {
+    let (line, newline) = strip_newline_marker(line);
+    let mut command = String::new();
+    let mut comment = None;
+    for keystroke in keystrokes(line)? {
+        match keystroke {
+            Keystroke::Grapheme(grapheme) => {
+                if grapheme == "#" {
+                    comment = comment.or(Some(command.len()));
+                }
+                command.push_str(&grapheme);
+            }
+            Keystroke::Literal(grapheme) | Keystroke::Escape(grapheme) => {
+                command.push_str(&grapheme)
+            }
+            Keystroke::Backspace(count) => {
+                for _ in 0..count {
+                    match command.grapheme_indices(true).next_back() {
+                        Some((index, _)) => command.truncate(index),
+                        None => break,
+                    }
+                }
+                comment = comment.map(|comment: usize| comment.min(command.len()));
+            }
+            // pasted at once
+            Keystroke::Pause(_) => {}
+        }
+    }
+
+    *time = time.saturating_add(micros(*step));
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: prompt_line(header, "", prompt, rprompt),
+    });
+
+    *time = time.saturating_add(micros(header.prompt_delay * step));
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: match comment {
+            Some(comment) => format!(
+                "{}\x1b[{}m{}\x1b[0m",
+                &command[..comment],
+                header.comment_sgr,
+                &command[comment..]
+            ),
+            None => command.clone(),
+        },
+    });
+
+    *time = time.saturating_add(micros(header.command_delay * step));
+    if newline {
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: header.newline.as_str().to_string(),
+        });
+    }
+
+    Ok(PreviewLine::Console {
+        prefix: String::new(),
+        prompt: prompt.to_string(),
+        command,
+        comment,
+        rprompt: rprompt.clone(),
+    }
+    .terminated(newline))
+}
+
+/// Split a streamed output line into the chunks printed one after the other:
+/// one per grapheme, escape sequences are printed with the next one (or the
+/// last one at the end of the line).
+fn output_chunks(line: &str) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    // 0: text, 1: after ESC, 2: in a control sequence (`ESC [`)
+    let mut escape = 0;
+    for grapheme in line.graphemes(true) {
+        chunk.push_str(grapheme);
+        escape = match (escape, grapheme) {
+            (0, "\x1b") => 1,
+            (1, "[") => 2,
+            (2, grapheme)
+                if !grapheme
+                    .chars()
+                    .all(|char| ('\x40'..='\x7e').contains(&char)) =>
+            {
+                2
+            }
+            (0, _) => {
+                chunks.push(std::mem::take(&mut chunk));
+                0
+            }
+            _ => 0,
+        };
+    }
+    match chunks.last_mut() {
+        Some(last) => last.push_str(&chunk),
+        None if !chunk.is_empty() => chunks.push(chunk),
+        None => {}
+    }
+    chunks
+}
+
+/// Print an output line one grapheme at a time (`> ` lines), `output_step`
+/// apart.
+fn stream_output_line(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    output_step: f64,
+    newline_data: Newline,
+    line: &str,
+) -> PreviewLine {
+    let (line, newline) = strip_newline_marker(line);
+    for chunk in output_chunks(line) {
+        *time = time.saturating_add(micros(output_step));
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: chunk,
+        });
+    }
+    if newline {
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: newline_data.as_str().to_string(),
+        });
+    }
+    PreviewLine::Output(line.to_string()).terminated(newline)
+}
+
+/// Number of cells of the bar of a `#progress:` line unless given.
+const PROGRESS_WIDTH: usize = 20;
+
+/// Print a progress bar (`#progress: <label> <frames>`) filling up over
+/// `frames` steps, each frame overwrites the previous one with a `\r`. The
+/// width of the bar and its fill character can follow as `width=<cells>` and
+/// `fill=<char>` (eg. `#progress: Downloading 20 width=30 fill==`).
+fn progress_line(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: f64,
+    newline: Newline,
+    indent: &str,
+    args: &str,
+) -> Result<PreviewLine, Error> {
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let mut width = PROGRESS_WIDTH;
+    let mut fill = "#";
+    while let Some(word) = words.last() {
+        if let Some(value) = word.strip_prefix("width=") {
+            width = value
+                .parse()
+                .ok()
+                .filter(|width| *width > 0)
+                .ok_or_else(|| format_err!("invalid progress bar width `{}`", value))?;
+        } else if let Some(value) = word.strip_prefix("fill=") {
+            if value.graphemes(true).count() != 1 {
+                return Err(format_err!(
+                    "progress bar fill `{}` must be a single character",
+                    value
+                ));
+            }
+            fill = value;
+        } else {
+            break;
+        }
+        words.pop();
+    }
+    let frames: usize = words
+        .pop()
+        .and_then(|frames| frames.parse().ok())
+        .filter(|frames| *frames > 0)
+        .ok_or_else(|| {
+            format_err!("`#progress:` needs a number of frames (eg. `#progress: Downloading 20`)")
+        })?;
+    let label = words.join(" ");
+
+    let mut line = String::new();
+    for frame in 0..=frames {
+        let filled = width * frame / frames;
+        line = format!(
+            "{}{}{}[{}{}] {:>3}%",
+            indent,
+            label,
+            if label.is_empty() { "" } else { " " },
+            fill.repeat(filled),
+            " ".repeat(width - filled),
+            100 * frame / frames
+        );
+        if frame > 0 {
+            *time = time.saturating_add(micros(step));
+        }
+        entries.push(Entry {
+            time: seconds(*time),
+            event_type: EventType::Output,
+            event_data: if frame == frames {
+                format!("\r{}{}", line, newline.as_str())
+            } else {
+                format!("\r{}", line)
+            },
+        });
+    }
+    Ok(PreviewLine::Output(line))
+}
+
+/// Type out a command in a shell entered with `#enter-shell:`, the prompt is
+/// printed as is after `indent`.
+fn echo_shell_line(
+    entries: &mut Vec<Entry>,
+    time: &mut Micros,
+    step: &f64,
+    typing: &mut Typing,
+    indent: &str,
+    prompt: &str,
+    line: &str,
+) -> Result<PreviewLine, Error> {
+    *time = time.saturating_add(micros(*step));
+
+    entries.push(Entry {
+        time: seconds(*time),
+        event_type: EventType::Output,
+        event_data: format!("{}{}", indent, prompt),
+    });
+
+    *time = time.saturating_add(micros(typing.prompt_delay * step));
+
+    let (command, comment) = echo_typing(entries, time, step, typing, line)?;
+
+    let mut spans = vec![(None, format!("{}{}", indent, prompt))];
+    match comment {
+        Some(comment) => {
+            spans.push((None, command[..comment].to_string()));
+            spans.push((
+                sgr_class(&typing.comment_sgr),
+                command[comment..].to_string(),
+            ));
+        }
+        None => spans.push((None, command)),
+    }
+    Ok(PreviewLine::Styled(spans).terminated(strip_newline_marker(line).1))
+}
+
+/// Where a `#toc` line was found, the table of contents is inserted there
+/// once all chapters are known.
+struct Toc {
+    entry: usize,
+    preview_line: usize,
+    time: Micros,
+}
+
+/// Render the table of contents listing every named chapter and marker
+/// (ordered by time) with the time it starts at.
+fn toc_lines(chapters: &[(String, Micros)]) -> Vec<(String, PreviewLine)> {
+    let width = chapters
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut lines = vec![(
+        "\x1b[1mContents\x1b[0m".to_string(),
+        PreviewLine::Styled(vec![(Some("bold".to_string()), "Contents".to_string())]),
+    )];
+    for (index, (label, time)) in chapters.iter().enumerate() {
+        let seconds = time / 1_000_000;
+        let number = format!("{:>3}. ", index + 1);
+        let label = format!("{:<width$} ", label, width = width);
+        let timestamp = format!("{:02}:{:02}", seconds / 60, seconds % 60);
+        lines.push((
+            format!("{}{}\x1b[36m{}\x1b[0m", number, label, timestamp),
+            PreviewLine::Styled(vec![
+                (None, number),
+                (None, label),
+                (Some("fg-6".to_string()), timestamp),
+            ]),
+        ));
+    }
+    lines
+}
+
+/// Metadata about the generated cast written with `--summary`.
+#[derive(Serialize)]
+pub struct Summary {
+    /// time of the latest entry, in seconds
+    pub duration: f64,
+    pub entries: usize,
+    pub commands: usize,
+    pub chapters: Vec<SummaryLabel>,
+    /// `#marker:` lines
+    pub markers: Vec<SummaryLabel>,
+    /// size of the terminal at the end of the cast
+    pub width: u32,
+    pub height: u32,
+    /// size of the cast file
+    pub bytes: usize,
+    pub scenario_hash: String,
+}
+
+/// A chapter or marker of the summary, its time is in seconds.
+#[derive(Serialize)]
+pub struct SummaryLabel {
+    pub label: String,
+    pub time: f64,
+}
+
+impl Summary {
+    pub fn new(
+        scenario: &Scenario,
+        options: &OutputOptions,
+        scenario_hash: String,
+    ) -> Result<Self, Error> {
+        let mut cast = vec![];
+        print_cast(&mut cast, &scenario.header, &scenario.entries, options)?;
+        Ok(Summary {
+            // `#entry:` lines can insert entries out of order
+            duration: scenario
+                .entries
+                .iter()
+                .map(|entry| entry.time)
+                .fold(0.0, f64::max),
+            entries: scenario.entries.len(),
+            commands: scenario.commands,
+            chapters: scenario
+                .chapters
+                .iter()
+                .map(|(label, time)| SummaryLabel {
+                    label: label.clone(),
+                    time: seconds(*time),
+                })
+                .collect(),
+            markers: scenario
+                .entries
+                .iter()
+                .filter(|entry| entry.event_type == EventType::Marker)
+                .map(|entry| SummaryLabel {
+                    label: entry.event_data.clone(),
+                    time: entry.time,
+                })
+                .collect(),
+            width: scenario.size.0,
+            height: scenario.size.1,
+            bytes: cast.len(),
+            scenario_hash,
+        })
+    }
+}
+
+/// Format `time` as a WebVTT timestamp, eg. `00:01:02.500`.
+fn vtt_timestamp(time: Micros) -> String {
+    let millis = time / 1_000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1_000 % 60,
+        millis % 1_000
+    )
+}
+
+/// Write the captions as WebVTT cues. A caption lasts until the next one
+/// starts, the last one until `end`. Empty captions only end the previous
+/// one.
+pub fn write_captions(path: &str, captions: &[(Micros, String)], end: Micros) -> Result<(), Error> {
+    let mut f = File::create(path)?;
+    writeln!(f, "WEBVTT")?;
+    let mut number = 0;
+    for (index, (start, text)) in captions.iter().enumerate() {
+        if text.is_empty() {
+            continue;
+        }
+        let stop = match captions.get(index + 1) {
+            Some((next, _)) => *next,
+            None => end.max(start.saturating_add(micros(CAPTION_MIN_DURATION))),
+        };
+        number += 1;
+        writeln!(f)?;
+        writeln!(f, "{}", number)?;
+        writeln!(f, "{} --> {}", vtt_timestamp(*start), vtt_timestamp(stop))?;
+        writeln!(f, "{}", text)?;
+    }
+    Ok(())
+}
+
+/// Duration of the last caption when nothing happens after it, in seconds.
+const CAPTION_MIN_DURATION: f64 = 2.0;
+
+/// Comparison operator of an `#assert-width:` or `#assert-lines:` directive.
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+/// An expectation about the rendered scenario declared in the scenario itself,
+/// eg. `#assert-width: <=80`.
+#[derive(Debug)]
+struct Assertion {
+    directive: &'static str,
+    expression: String,
+    comparison: Comparison,
+    value: usize,
+    line: Location,
+}
+
+impl Assertion {
+    fn parse(directive: &'static str, expression: &str, line: Location) -> Result<Self, Error> {
+        let expression = expression.trim();
+        let (comparison, value) = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("==", Comparison::Equal),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .iter()
+        .find_map(|(operator, comparison)| {
+            expression
+                .strip_prefix(operator)
+                .map(|value| (*comparison, value))
+        })
+        .unwrap_or((Comparison::Equal, expression));
+        let value = value
+            .trim()
+            .parse()
+            .map_err(|_| format_err!("invalid assertion `{} {}`", directive, expression))?;
+        Ok(Assertion {
+            directive,
+            expression: expression.to_string(),
+            comparison,
+            value,
+            line,
+        })
+    }
+
+    fn holds(&self, actual: usize) -> bool {
+        match self.comparison {
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Equal => actual == self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+            Comparison::Greater => actual > self.value,
+        }
+    }
+}
+
+/// The widest line and the largest number of lines shown on a screen
+/// (between two clears) of the preview lines added so far, checked by the
+/// assertions.
+#[derive(Default)]
+struct Layout {
+    width: usize,
+    lines: usize,
+    /// number of lines of the current screen
+    screen_lines: usize,
+}
+
+impl Layout {
+    fn add(&mut self, preview_line: &PreviewLine, tabstop: usize) {
+        self.width = self.width.max(preview_line.width(tabstop));
+        match preview_line {
+            PreviewLine::Clear => self.screen_lines = 0,
+            PreviewLine::Resize { .. } | PreviewLine::Unterminated(_) => {}
+            _ => {
+                self.screen_lines += 1;
+                self.lines = self.lines.max(self.screen_lines);
+            }
+        }
+    }
+}
+
+/// Check the assertions against the `layout` of the preview. Failures are
+/// logged, or returned as an error when `strict` is set.
+fn check_assertions(assertions: &[Assertion], layout: &Layout, strict: bool) -> Result<(), Error> {
+    for assertion in assertions {
+        let actual = match assertion.directive {
+            "#assert-width:" => layout.width,
+            _ => layout.lines,
+        };
+        if !assertion.holds(actual) {
+            let message = format!(
+                "assertion `{} {}` on line {} failed, actual value is {}",
+                assertion.directive, assertion.expression, assertion.line, actual
+            );
+            if strict {
+                return Err(format_err!("{}", message));
+            }
+            warn!("{}", message);
+        }
+    }
+    Ok(())
+}
+
+/// Directives supported in the body of a scenario and what they do.
+pub const DIRECTIVES: &[(&str, &str)] = &[
+    (
+        "#timeout:",
+        "wait for the given duration (eg. `2.5`, `500ms`, `1m`)",
+    ),
+    (
+        "#at:",
+        "wait until this time since the start of the cast (eg. `12.5`, `1m`)",
+    ),
+    (
+        "#pause",
+        "a long pause of `pause_gap` steps, eg. waiting for the presenter",
+    ),
+    ("#bell", "ring the terminal bell"),
+    ("#flash", "flash the screen in reverse video for a step"),
+    (
+        "#>",
+        "type the rest of the line after a prompt as a shell comment",
+    ),
+    (
+        "#typing-profile:",
+        "type commands like a human (fast, average, hunt-peck or uniform)",
+    ),
+    (
+        "#typing:",
+        "type the following commands slow, normal or fast (see `slow_factor` and `fast_factor`)",
+    ),
+    (
+        "#speed:",
+        "set the step of the following lines (eg. `0.3`), `reset` restores it",
+    ),
+    ("#banner:", "display the text in large ASCII-art letters"),
+    (
+        "#chapter:",
+        "clear the screen and start a new (named) chapter",
+    ),
+    (
+        "#toc",
+        "display a table of contents of all named chapters and markers",
+    ),
+    ("#log:", "display a log record, eg. `INFO starting server`"),
+    (
+        "#progress:",
+        "display a progress bar filling up over a number of steps, eg. `Downloading 20`",
+    ),
+    (
+        "#assert-width:",
+        "check the width of the widest line, eg. `<=80`",
+    ),
+    (
+        "#assert-lines:",
+        "check the number of lines shown on a screen, eg. `<=20`",
+    ),
+    (
+        "#cursor-shape:",
+        "set the cursor shape (block, underline or bar, optionally blink)",
+    ),
+    (
+        "#enter-shell:",
+        "run the following commands in a shell with this prompt",
+    ),
+    ("#exit-shell", "leave the shell of the last #enter-shell:"),
+    (
+        "#set:",
+        "set a variable used as `${NAME}` in commands and output, eg. `#set: VERSION=1.2`",
+    ),
+    ("#entry:", "insert a raw asciicast entry (see --rebase)"),
+    (
+        "#echo:",
+        "print the rest of the line as is, without markup or variables",
+    ),
+    (
+        "#marker:",
+        "add a marker with this label players can jump to",
+    ),
+    ("#resize:", "resize the terminal, eg. `120x40`"),
+    (
+        "#embed:",
+        "insert the events of a recorded asciicast v2 file, `resize` after the path resizes the terminal to its size",
+    ),
+    (
+        "#include:",
+        "insert the lines of another scenario (relative to this one)",
+    ),
+    (
+        "#caption:",
+        "show a caption (see --captions), empty to hide it",
+    ),
+    ("#note:", "same as #caption:"),
+    (
+        "#hint:",
+        "show a hint over the animated SVG preview for `hint_duration` seconds",
+    ),
+    (
+        "#shuffle-begin",
+        "print the following output lines in a random order",
+    ),
+    ("#shuffle-end", "end a #shuffle-begin block"),
+];
+
+/// Shorter aliases of directives, they never shadow a directive's own name.
+pub const ALIASES: &[(&str, &str)] = &[
+    ("#wait:", "#timeout:"),
+    ("#profile:", "#typing-profile:"),
+    ("#ch:", "#chapter:"),
+];
+
+/// Whether the cursor is shown during `line` when the header sets
+/// `manage_cursor`: while a command is typed but not while output is printed
+/// or during a pause. `None` for the lines leaving it as it is.
+fn cursor_shown(line: &str, header: &ScenarioHeader) -> Option<bool> {
+    const PAUSES: &[&str] = &["#timeout:", "#pause", "#at:"];
+    const OUTPUTS: &[&str] = &["#log:", "#progress:", "#banner:", "#shuffle-end", "#echo:"];
+    if line.starts_with("$ ")
+        || line.starts_with("$! ")
+        || line.starts_with("#>")
+        || header
+            .prompt_prefixes
+            .iter()
+            .any(|prompt_prefix| line.starts_with(prompt_prefix.as_str()))
+    {
+        Some(true)
+    } else if line.trim().is_empty()
+        || PAUSES
+            .iter()
+            .chain(OUTPUTS)
+            .any(|directive| line.starts_with(directive))
+    {
+        Some(false)
+    } else if line.starts_with('#') || line.starts_with("--") {
+        None
+    } else {
+        Some(false)
+    }
+}
+
+/// Turn a line of a `--from-commands` file into a scenario line: blank
+/// lines and lines starting with `#` are kept as they are, everything else
+/// is a command.
+fn command_line(line: String) -> String {
+    if line.trim().is_empty() || line.starts_with('#') {
+        line
+    } else {
+        format!("$ {}", line)
+    }
+}
+
+/// Languages of the Markdown code blocks used by `--from-markdown`.
+const MARKDOWN_LANGUAGES: &[&str] = &["console", "shell", "sh", "shell-session"];
+
+/// Turns the lines of a `--from-markdown` file into scenario lines: only the
+/// content of the shell code blocks is kept and every block starts on a
+/// cleared display.
+#[derive(Default)]
+struct Markdown {
+    /// whether the current line is in a code block, and if it is a shell one
+    block: Option<bool>,
+    /// number of shell code blocks seen so far
+    count: usize,
+}
+
+impl Markdown {
+    fn scenario_line(&mut self, line: String) -> Option<String> {
+        let fence = match line.trim_start().strip_prefix("```") {
+            Some(fence) => fence.trim(),
+            // only `$ ` lines are commands, the rest of a shell block is
+            // printed as is (eg. `--- a/file` of a diff)
+            None if self.block != Some(true) => return None,
+            None if line.starts_with("$ ") => return Some(line),
+            None => return Some(format!("#echo: {}", line)),
+        };
+        if self.block.is_some() {
+            self.block = None;
+            return None;
+        }
+        let language = fence.split_whitespace().next().unwrap_or_default();
+        let shell = MARKDOWN_LANGUAGES.contains(&language);
+        self.block = Some(shell);
+        if !shell {
+            return None;
+        }
+        self.count += 1;
+        Some("--".to_string()).filter(|_| self.count > 1)
+    }
+}
+
+/// The unknown directive (eg. a misspelled `#timout:`) a line starts with,
+/// comments like `# note: ...` have a space before the colon.
+fn unknown_directive(line: &str) -> Option<&str> {
+    let directive = &line[..line.find(':')? + 1];
+    if directive.len() == 2
+        || directive.contains(char::is_whitespace)
+        || DIRECTIVES.iter().any(|(name, _)| *name == directive)
+    {
+        None
+    } else {
+        Some(directive)
+    }
+}
+
+/// Rewrite a line using a directive alias into its canonical form.
+fn resolve_alias(line: String) -> String {
+    for (alias, directive) in ALIASES {
+        if let Some(stripped) = line.strip_prefix(alias) {
+            return format!("{}{}", directive, stripped);
+        }
+    }
+    line
+}
+
+/// How deep `#include:`s can be nested.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Add `line` to `lines` or, for an `#include:` line, the lines of the
+/// scenario it includes (without its header), relative to `directory`.
+/// `includes` are the files being included, to refuse include cycles, and
+/// every included (or embedded) file is added to `included`.
+fn include(
+    lines: &mut Vec<(Location, String)>,
+    location: Location,
+    line: String,
+    directory: &Path,
+    includes: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    // the path of an embedded cast is relative to the scenario it is in
+    if let Some(stripped) = line.strip_prefix("#embed:") {
+        let (name, resize) = embed_arguments(stripped);
+        let path = directory.join(name);
+        if let Ok(path) = path.canonicalize() {
+            if !included.contains(&path) {
+                included.push(path);
+            }
+        }
+        let resize = if resize { " resize" } else { "" };
+        lines.push((location, format!("#embed: {}{}", path.display(), resize)));
+        return Ok(());
+    }
+    let name = match line.strip_prefix("#include:") {
+        Some(name) => name.trim(),
+        None => {
+            lines.push((location, line));
+            return Ok(());
+        }
+    };
+    let path = directory.join(name);
+    let file = path
+        .canonicalize()
+        .and_then(|path| Ok((File::open(&path)?, path)));
+    let (file, path) = file
+        .map_err(|e| format_err!("can not include `{}`: {}", path.display(), e))
+        .map_err(at_line(&location))?;
+    if includes.contains(&path) {
+        return Err(format_err!(
+            "line {}: include cycle, `{}` is already being included",
+            location,
+            name
+        ));
+    }
+    if includes.len() > MAX_INCLUDE_DEPTH {
+        return Err(format_err!(
+            "line {}: `#include:` nested more than {} times",
+            location,
+            MAX_INCLUDE_DEPTH
+        ));
+    }
+
+    let directory = path.parent().unwrap_or(directory).to_path_buf();
+    if !included.contains(&path) {
+        included.push(path.clone());
+    }
+    includes.push(path);
+    let parent = Rc::new((name.to_string(), location));
+    // only the header of the main scenario is used, the front matter starts
+    // on the first line or after a shebang
+    let mut file_lines = BufReader::new(file).lines().enumerate().peekable();
+    if let Some((_, Ok(line))) = file_lines.peek() {
+        if is_shebang(line) {
+            file_lines.next();
+        }
+    }
+    let (_, taken) = take_front_matter(&mut file_lines);
+    for (index, maybe_line) in taken.into_iter().chain(file_lines) {
+        let location = Location {
+            line: index + 1,
+            include: Some(parent.clone()),
+        };
+        let line = resolve_alias(maybe_line.map_err(|e| at_line(&location)(e.into()))?);
+        if index == 0 && line.starts_with("#! ") {
+            continue;
+        }
+        include(lines, location, line, &directory, includes, included)?;
+    }
+    includes.pop();
+    Ok(())
+}
+
+/// The lines of the input after its header with the lines of `#include:`d
+/// scenarios and where they come from, read as they are needed.
+struct ScenarioLines<'a, I> {
+    lines: I,
+    options: &'a RenderOptions,
+    /// the directory of the scenario file
+    directory: PathBuf,
+    /// the files being included, see `include`
+    includes: Vec<PathBuf>,
+    /// every included (or embedded) file so far
+    included: Vec<PathBuf>,
+    markdown: Markdown,
+    /// the lines of the last input line (eg. of an included scenario) not
+    /// read yet
+    pending: VecDeque<(Location, String)>,
+}
+
+impl<'a, I> ScenarioLines<'a, I>
+where
+    I: Iterator<Item = (usize, io::Result<String>)>,
+{
+    fn new(lines: I, options: &'a RenderOptions) -> Self {
+        let directory = match &options.file {
+            Some(file) => file.parent().unwrap_or_else(|| Path::new("")),
+            None => Path::new(""),
+        };
+        ScenarioLines {
+            lines,
+            options,
+            directory: directory.to_path_buf(),
+            includes: options
+                .file
+                .iter()
+                .filter_map(|file| file.canonicalize().ok())
+                .collect(),
+            included: vec![],
+            markdown: Markdown::default(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Read the next line of the input into the pending lines, `false` at
+    /// the end of the input.
+    fn read_line(&mut self) -> Result<bool, Error> {
+        let (index, maybe_line) = match self.lines.next() {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+        let location = Location {
+            line: index + 1,
+            include: None,
+        };
+        let mut line = resolve_alias(maybe_line.map_err(|e| at_line(&location)(e.into()))?);
+        if self.options.format == InputFormat::Commands {
+            line = command_line(line);
+        }
+        if self.options.format == InputFormat::Markdown {
+            line = match self.markdown.scenario_line(line) {
+                Some(line) => line,
+                None => return Ok(true),
+            };
+        }
+        // skip when first line starts with "#! " since we already processed it above
+        if index == 0 && line.starts_with("#!") {
+            return Ok(true);
+        }
+        let mut lines = vec![];
+        include(
+            &mut lines,
+            location,
+            line,
+            &self.directory,
+            &mut self.includes,
+            &mut self.included,
+        )?;
+        self.pending.extend(lines);
+        Ok(true)
+    }
+}
+
+impl<'a, I> Iterator for ScenarioLines<'a, I>
+where
+    I: Iterator<Item = (usize, io::Result<String>)>,
+{
+    type Item = Result<(Location, String), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            match self.read_line() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// The files `#include:`d (or `#embed:`ded) by a scenario, without rendering
+/// it, eg. to hash them along with the scenario.
+pub fn included_files(input: impl BufRead, options: &RenderOptions) -> Result<Vec<PathBuf>, Error> {
+    let mut lines = input.lines().enumerate().peekable();
+    let taken = match options.format {
+        InputFormat::Scenario => take_header(&mut lines)?.1,
+        _ => vec![],
+    };
+    let mut scenario_lines = ScenarioLines::new(taken.into_iter().chain(lines), options);
+    for line in &mut scenario_lines {
+        line?;
+    }
+    Ok(scenario_lines.included)
+}
+
+/// How the lines of the input are turned into a cast.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// a scenario, optionally starting with a `#!` header line
+    Scenario,
+    /// a plain list of commands, see `--from-commands`
+    Commands,
+    /// the shell code blocks of a Markdown file, see `--from-markdown`
+    Markdown,
+}
+
+/// Options changing how a scenario is rendered.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub format: InputFormat,
+    /// overrides `initial_offset` of the header
+    pub initial_offset: Option<f64>,
+    /// overrides `start_delay` of the header
+    pub start_delay: Option<f64>,
+    /// overrides `width` of the header
+    pub width: Option<u32>,
+    /// overrides `height` of the header
+    pub height: Option<u32>,
+    /// overrides `step` of the header
+    pub step: Option<f64>,
+    /// overrides `theme` of the header
+    pub theme: Option<String>,
+    /// sets `preview_truncate` of the header
+    pub preview_truncate: bool,
+    /// seed for the random number generator used by typing profiles, jitter
+    /// and shuffle blocks
+    pub seed: Option<u64>,
+    /// variables used as `${NAME}` in commands and output, they take
+    /// precedence over `#set:` lines
+    pub variables: Vec<(String, String)>,
+    /// maximum relative deviation of a keystroke from `step`, between 0 and 1
+    pub jitter: f64,
+    /// the scenario file, `#include:` paths are relative to its directory
+    /// (or to the current directory without one)
+    pub file: Option<PathBuf>,
+    /// the time of `#entry:` lines is a delay after the previous event
+    pub rebase: bool,
+    /// fail when an assertion does not hold or on unknown directives instead
+    /// of logging a warning
+    pub strict: bool,
+    /// start a new clip on every `--` and `#chapter:` instead of clearing
+    /// the display
+    pub split: bool,
+    /// fail on unknown directives (eg. a misspelled `#timout:`), see `--check`
+    pub check: bool,
+    /// playback speed, `2.0` halves every delay
+    pub speed: f64,
+    /// remove the escape sequences (eg. colors) written in the scenario
+    pub strip_ansi: bool,
+    /// keep the lines of the SVG previews, see [`Scenario::preview_lines`],
+    /// without them memory doesn't grow with the number of lines
+    pub preview: bool,
+    #[cfg(feature = "banner")]
+    pub banner_font: String,
+    #[cfg(feature = "banner")]
+    pub banner_color: Option<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            format: InputFormat::Scenario,
+            initial_offset: None,
+            start_delay: None,
+            width: None,
+            height: None,
+            step: None,
+            theme: None,
+            preview_truncate: false,
+            seed: None,
+            variables: vec![],
+            jitter: 0.0,
+            file: None,
+            rebase: false,
+            strict: false,
+            split: false,
+            check: false,
+            speed: 1.0,
+            strip_ansi: false,
+            preview: true,
+            #[cfg(feature = "banner")]
+            banner_font: "block".to_string(),
+            #[cfg(feature = "banner")]
+            banner_color: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Override the fields of `header` set by the options.
+    pub fn override_header(&self, header: &mut ScenarioHeader) {
+        if let Some(initial_offset) = self.initial_offset {
+            header.initial_offset = initial_offset;
+        }
+        if let Some(start_delay) = self.start_delay {
+            header.start_delay = start_delay;
+        }
+        if let Some(width) = self.width {
+            header.width = width;
+        }
+        if let Some(height) = self.height {
+            header.height = height;
+        }
+        if let Some(step) = self.step {
+            header.step = step;
+        }
+        if let Some(theme) = &self.theme {
+            header.theme = theme.clone();
+        }
+        if self.preview_truncate {
+            header.preview_truncate = true;
+        }
+    }
+}
+
+/// A rendered scenario.
+#[derive(Debug)]
+pub struct Scenario {
+    pub header: ScenarioHeader,
+    /// entries of the cast, empty when splitting into clips
+    pub entries: Vec<Entry>,
+    /// the clips when splitting, see [`RenderOptions::split`]
+    pub clips: Vec<Clip>,
+    pub preview_lines: Vec<PreviewLine>,
+    /// when each of the preview lines is shown
+    pub preview_times: Vec<Micros>,
+    /// named chapters with the time they start at
+    pub chapters: Vec<(String, Micros)>,
+    /// `#caption:` and `#note:` lines with their time
+    pub captions: Vec<(Micros, String)>,
+    /// `#hint:` lines with their time, see [`animated_svg_preview`]
+    pub hints: Vec<(Micros, String)>,
+    /// number of typed commands
+    pub commands: usize,
+    /// size of the terminal after the last line of the scenario
+    pub size: (u32, u32),
+    /// time after the last line of the scenario
+    pub end: Micros,
+    /// the files `#include:`d by the scenario
+    pub included: Vec<PathBuf>,
+}
+
+/// Render a scenario into asciicast entries using the default options.
+pub fn render_scenario(input: impl BufRead) -> Result<Vec<Entry>, Error> {
+    Ok(render(input, &RenderOptions::default())?.entries)
+}
+
+/// Check the values of the `header` which can't be checked while parsing it.
+fn check_header(header: &ScenarioHeader) -> Result<(), Error> {
+    if let Some(prompt_prefix) = header
+        .prompt_prefixes
+        .iter()
+        .find(|prompt_prefix| !prompt_prefix.ends_with("$ ") || *prompt_prefix == "$ ")
+    {
+        return Err(format_err!(
+            "prompt prefix `{}` must end with `$ ` (eg. `(venv) $ `)",
+            prompt_prefix
+        ));
+    }
+    if header.tabstop == 0 {
+        return Err(format_err!("`tabstop` must be at least 1"));
+    }
+    if header.font_size == 0 {
+        return Err(format_err!("`font_size` must be at least 1"));
+    }
+    let numbers = [
+        ("step", Some(header.step)),
+        ("initial_offset", Some(header.initial_offset)),
+        ("start_delay", Some(header.start_delay)),
+        ("idle_time_limit", header.idle_time_limit),
+        ("output_step", header.output_step),
+        ("blank_pause", Some(header.blank_pause)),
+        ("pause_gap", Some(header.pause_gap)),
+        ("clear_pre_pause", Some(header.clear_pre_pause)),
+        ("clear_post_pause", Some(header.clear_post_pause)),
+        ("chain_pause", Some(header.chain_pause)),
+        ("prompt_delay", Some(header.prompt_delay)),
+        ("command_delay", Some(header.command_delay)),
+        ("slow_factor", Some(header.slow_factor)),
+        ("fast_factor", Some(header.fast_factor)),
+    ];
+    for (name, number) in numbers
+        .iter()
+        .filter_map(|(name, number)| number.map(|number| (name, number)))
+    {
+        if !(number.is_finite() && number >= 0.0) {
+            return Err(format_err!(
+                "`{}` `{}` must be a number, not negative",
+                name,
+                number
+            ));
+        }
+    }
+    if !(header.max_timeout.is_finite() && header.max_timeout >= 0.0) {
+        return Err(format_err!(
+            "`max_timeout` `{}` must be a number of seconds",
+            header.max_timeout
+        ));
+    }
+    // the waits in seconds of the fields in steps
+    let step = header.step;
+    let waits = [
+        ("step", step),
+        ("start_delay", header.start_delay),
+        ("initial_offset", header.initial_offset * step),
+        ("output_step", header.output_step.unwrap_or(step / 4.0)),
+        ("blank_pause", header.blank_pause * step),
+        ("pause_gap", header.pause_gap * step),
+        ("clear_pre_pause", header.clear_pre_pause * step),
+        ("clear_post_pause", header.clear_post_pause * step),
+        ("chain_pause", header.chain_pause * step),
+        ("prompt_delay", header.prompt_delay * step),
+        ("command_delay", header.command_delay * step),
+        ("slow_factor", header.slow_factor * step),
+        ("fast_factor", header.fast_factor * step),
+    ];
+    for (name, wait) in waits.iter() {
+        check_timeout(header.max_timeout, name, *wait)?;
+    }
+    if !(header.hint_duration.is_finite() && header.hint_duration >= 0.0) {
+        return Err(format_err!(
+            "`hint_duration` `{}` must be a number of seconds",
+            header.hint_duration
+        ));
+    }
+    theme(&header.theme)?;
+    Ok(())
+}
+
+/// Check that the wait of `duration` seconds added by a `directive` line is
+/// a number of seconds not longer than `max_timeout` of the header, eg. a
+/// typo like `#timeout: 1e9`.
+fn check_timeout(max_timeout: f64, directive: &str, duration: f64) -> Result<(), Error> {
+    if !(duration.is_finite() && duration >= 0.0) {
+        return Err(format_err!(
+            "`{}` wait of {}s must be a number of seconds, not negative",
+            directive,
+            duration
+        ));
+    }
+    if duration > max_timeout {
+        return Err(format_err!(
+            "`{}` wait of {}s is longer than `max_timeout` ({}s)",
+            directive,
+            duration,
+            max_timeout
+        ));
+    }
+    Ok(())
+}
+
+/// Render a scenario (or a list of commands or a Markdown file, depending on
+/// `options.format`).
+pub fn render(input: impl BufRead, options: &RenderOptions) -> Result<Scenario, Error> {
+    let mut lines = input.lines().enumerate().peekable();
+
+    // Header, a `---` line not opening a front matter is read again
+    let (header, taken) = match options.format {
+        InputFormat::Scenario => take_header(&mut lines)?,
+        _ => (ScenarioHeader::default(), vec![]),
+    };
+    let mut renderer = Renderer::new(header, options)?;
+
+    // The rest of the file
+    let mut scenario_lines = ScenarioLines::new(taken.into_iter().chain(lines), options);
+    for line in &mut scenario_lines {
+        let (location, line) = line?;
+        renderer.line(location, line)?;
+    }
+    renderer.finish(scenario_lines.included)
+}
+
+/// Render a scenario like [`render`], writing its cast to `out` line after
+/// line rather than once the whole scenario is rendered. The entries are
+/// only held back when the rest of the scenario can still move them, that is
+/// with an `idle_time_limit` or once a `#toc` line is met. The header has no
+/// `duration`, and the returned scenario has no entries.
+pub fn stream(
+    input: impl BufRead,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+    output_options: &OutputOptions,
+) -> Result<Scenario, Error> {
+    if options.split {
+        return Err(format_err!("a scenario split into clips can't be streamed"));
+    }
+    let mut lines = input.lines().enumerate().peekable();
+
+    // Header, a `---` line not opening a front matter is read again
+    let (header, taken) = match options.format {
+        InputFormat::Scenario => take_header(&mut lines)?,
+        _ => (ScenarioHeader::default(), vec![]),
+    };
+    let mut renderer = Renderer::new(header, options)?;
+    let mut writer = CastWriter::new(out, &renderer.header, output_options)?;
+
+    // The rest of the file
+    let mut scenario_lines = ScenarioLines::new(taken.into_iter().chain(lines), options);
+    for line in &mut scenario_lines {
+        let (location, line) = line?;
+        renderer.line(location, line)?;
+        for entry in renderer.final_entries() {
+            writer.write(&entry)?;
+        }
+    }
+    let mut scenario = renderer.finish(scenario_lines.included)?;
+    for entry in scenario.entries.drain(..) {
+        writer.write(&entry)?;
+    }
+    Ok(scenario)
+}
+
+/// A scenario being rendered line after line, by [`render`] and by
+/// [`ScenarioBuilder`].
+pub(crate) struct Renderer<'a> {
+    options: &'a RenderOptions,
+    header: ScenarioHeader,
+    entries: Vec<Entry>,
+    preview_lines: Vec<PreviewLine>,
+    /// when each of the preview lines is shown
+    preview_times: Vec<Micros>,
+    clips: Vec<Clip>,
+    /// label of the current clip
+    clip_label: Option<String>,
+    time: Micros,
+    /// time of a step, changed by `#speed:` and `#typing-profile:`
+    step: f64,
+    typing: Typing,
+    assertions: Vec<Assertion>,
+    /// the layout of the preview lines dropped so far, see
+    /// [`RenderOptions::preview`]
+    layout: Layout,
+    chapters: Vec<(String, Micros)>,
+    tocs: Vec<Toc>,
+    /// the markers of the entries handed out by `final_entries`, with their
+    /// time once scaled to the speed
+    markers: Vec<(String, Micros)>,
+    /// number of entries handed out by `final_entries`
+    streamed: usize,
+    captions: Vec<(Micros, String)>,
+    hints: Vec<(Micros, String)>,
+    /// the `#shuffle-begin` line of the open block, with its lines
+    shuffle: Option<(Location, Vec<String>)>,
+    /// prompts of the `#enter-shell:` nested shells
+    shells: Vec<String>,
+    commands: usize,
+    size: (u32, u32),
+    /// whether the cursor is shown, see `manage_cursor`
+    cursor: bool,
+    variables: HashMap<String, String>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Start rendering with `header`, once overridden by the `options`.
+    pub(crate) fn new(
+        mut header: ScenarioHeader,
+        options: &'a RenderOptions,
+    ) -> Result<Self, Error> {
+        options.override_header(&mut header);
+        check_header(&header)?;
+        if !(options.speed.is_finite() && options.speed > 0.0) {
+            return Err(format_err!(
+                "speed `{}` must be a number greater than 0",
+                options.speed
+            ));
+        }
+        if !(0.0..1.0).contains(&options.jitter) {
+            return Err(format_err!(
+                "jitter `{}` must be at least 0 and less than 1",
+                options.jitter
+            ));
+        }
+        Ok(Renderer {
+            options,
+            entries: vec![],
+            preview_lines: vec![],
+            preview_times: vec![],
+            clips: vec![],
+            clip_label: None,
+            time: header.start_time(),
+            step: header.step,
+            typing: Typing::new(&header, options.seed, options.jitter),
+            assertions: vec![],
+            layout: Layout::default(),
+            chapters: vec![],
+            tocs: vec![],
+            markers: vec![],
+            streamed: 0,
+            captions: vec![],
+            hints: vec![],
+            shuffle: None,
+            shells: vec![],
+            commands: 0,
+            size: (header.width, header.height),
+            cursor: true,
+            variables: options.variables.iter().cloned().collect(),
+            header,
+        })
+    }
+
+    /// Start a line: the lines added to the preview by the previous line are
+    /// shown once it is done, and with `manage_cursor` the cursor is `shown`
+    /// or hidden during the line (`None` leaves it as it is).
+    pub(crate) fn start_line(&mut self, shown: Option<bool>) {
+        self.preview_times
+            .resize(self.preview_lines.len(), self.time);
+        // without a preview only the layout is kept, unless a table of
+        // contents is inserted among the lines later on
+        if !self.options.preview && self.tocs.is_empty() {
+            for preview_line in self.preview_lines.drain(..) {
+                self.layout.add(&preview_line, self.header.tabstop);
+            }
+            self.preview_times.clear();
+        }
+        if self.header.manage_cursor {
+            if let Some(shown) = shown.filter(|shown| *shown != self.cursor) {
+                self.entries.push(cursor_entry(self.time, shown));
+                self.cursor = shown;
+            }
+        }
+    }
+
+    /// Render a line of the scenario.
+    fn line(&mut self, location: Location, line: String) -> Result<(), Error> {
+        // "${NAME}" in commands and output is replaced by the variable's value
+        let line = if line.starts_with('#') {
+            line
+        } else {
+            substitute(&line, &self.variables, self.options.strict, &location)?
+        };
+        let line = if self.options.strip_ansi {
+            strip_ansi(&line)
+        } else {
+            line
+        };
+
+        // with `manage_cursor` the cursor is hidden unless a command is typed
+        self.start_line(cursor_shown(&line, &self.header));
+
+        // lines starting with "#timeout: " will create defined timeout
+        if let Some(stripped) = line.strip_prefix("#timeout:") {
+            let timeout = parse_duration(stripped).map_err(at_line(&location))?;
+            self.timeout(timeout).map_err(at_line(&location))?;
+
+        // lines starting with "#at:" move on to an absolute time, which can't
+        // be before the current one
+        } else if let Some(stripped) = line.strip_prefix("#at:") {
+            let at = micros(parse_duration(stripped).map_err(at_line(&location))?);
+            if at < self.time {
+                return Err(format_err!(
+                    "line {}: `#at:` time {}s is before the current time {}s",
+                    location,
+                    seconds(at),
+                    seconds(self.time)
+                ));
+            }
+            check_timeout(self.header.max_timeout, "#at:", seconds(at - self.time))
+                .map_err(at_line(&location))?;
+            self.time = at;
+
+        // "#pause" lines wait for the presenter, a long pause of `pause_gap`
+        // steps
+        } else if line.trim_end() == "#pause" {
+            let pause = self.header.pause_gap * self.step;
+            check_timeout(self.header.max_timeout, "#pause", pause).map_err(at_line(&location))?;
+            self.time = self.time.saturating_add(micros(pause));
+
+        // "#bell" lines ring the terminal bell
+        } else if line.trim_end() == "#bell" {
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Output,
+                event_data: "\x07".to_string(),
+            });
+
+        // "#flash" lines flash the screen, in reverse video for a step
+        } else if line.trim_end() == "#flash" {
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Output,
+                event_data: "\x1b[?5h".to_string(),
+            });
+            self.time = self.time.saturating_add(micros(self.step));
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Output,
+                event_data: "\x1b[?5l".to_string(),
+            });
+
+        // lines starting with "#typing-profile:" change how commands are typed
+        } else if let Some(stripped) = line.strip_prefix("#typing-profile:") {
+            match stripped.trim() {
+                "uniform" => {
+                    self.typing.profile = None;
+                    self.step = self.header.step;
+                }
+                name => {
+                    let profile = TypingProfile::from_name(name).map_err(at_line(&location))?;
+                    self.typing.profile = Some(profile);
+                    self.step = profile.step;
+                }
+            }
+
+        // lines starting with "#typing:" make the following commands typed
+        // slower or faster, the pauses around them stay the same
+        } else if let Some(stripped) = line.strip_prefix("#typing:") {
+            self.typing.factor = match stripped.trim() {
+                "slow" => self.header.slow_factor,
+                "normal" => 1.0,
+                "fast" => self.header.fast_factor,
+                speed => {
+                    return Err(format_err!(
+                        "line {}: unknown typing speed `{}` (expected slow, normal or fast)",
+                        location,
+                        speed
+                    ));
+                }
+            };
+
+        // lines starting with "#speed:" change the step of the following lines
+        } else if let Some(stripped) = line.strip_prefix("#speed:") {
+            self.step = match stripped.trim() {
+                "reset" => self.header.step,
+                value => parse_duration(value).map_err(at_line(&location))?,
+            };
+            check_timeout(self.header.max_timeout, "#speed:", self.step)
+                .map_err(at_line(&location))?;
+
+        // lines starting with "#banner:" display the text in large letters
+        } else if let Some(stripped) = line.strip_prefix("#banner:") {
+            #[cfg(feature = "banner")]
+            {
+                let color = match &self.options.banner_color {
+                    Some(name) => Some(format!(
+                        "\x1b[{}m",
+                        30 + color_index(name).map_err(at_line(&location))?
+                    )),
+                    None => None,
+                };
+                for row in banner::render(stripped, &self.options.banner_font)
+                    .map_err(at_line(&location))?
+                {
+                    self.entries.push(Entry {
+                        time: seconds(self.time),
+                        event_type: EventType::Output,
+                        event_data: match &color {
+                            Some(color) => {
+                                format!("{}{}\x1b[0m{}", color, row, self.header.newline.as_str())
+                            }
+                            None => format!("{}{}", row, self.header.newline.as_str()),
+                        },
+                    });
+                    self.preview_lines.push(PreviewLine::Output(row));
+                }
+            }
+            #[cfg(not(feature = "banner"))]
+            warn!(
+                "ignoring `#banner:{}` on line {}, rebuild with `--features banner` to enable it",
+                stripped, location
+            );
+
+        // lines starting with "#assert-width:" or "#assert-lines:" declare
+        // expectations about the rendered scenario
+        } else if let Some(stripped) = line.strip_prefix("#assert-width:") {
+            self.assertions.push(
+                Assertion::parse("#assert-width:", stripped, location.clone())
+                    .map_err(at_line(&location))?,
+            );
+        } else if let Some(stripped) = line.strip_prefix("#assert-lines:") {
+            self.assertions.push(
+                Assertion::parse("#assert-lines:", stripped, location.clone())
+                    .map_err(at_line(&location))?,
+            );
+
+        // lines starting with "#progress:" display a progress bar filling up
+        } else if let Some(stripped) = line.strip_prefix("#progress:") {
+            let indent = " ".repeat(self.shells.len() * self.header.shell_indent);
+            self.preview_lines.push(
+                progress_line(
+                    &mut self.entries,
+                    &mut self.time,
+                    self.step,
+                    self.header.newline,
+                    &indent,
+                    stripped,
+                )
+                .map_err(at_line(&location))?,
+            );
+
+        // lines starting with "#log:" display a log record
+        } else if let Some(stripped) = line.strip_prefix("#log:") {
+            let (output, preview_line) =
+                log_line(&self.header, self.time, stripped).map_err(at_line(&location))?;
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Output,
+                event_data: format!("{}{}", output, self.header.newline.as_str()),
+            });
+            self.preview_lines.push(preview_line);
+
+        // lines starting with "#cursor-shape:" change the cursor shape
+        } else if let Some(stripped) = line.strip_prefix("#cursor-shape:") {
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Output,
+                event_data: cursor_shape(stripped).map_err(at_line(&location))?,
+            });
+
+        // lines starting with "#set:" set a variable, unless given in the
+        // options
+        } else if let Some(stripped) = line.strip_prefix("#set:") {
+            let (name, value) = parse_variable(stripped).map_err(at_line(&location))?;
+            if !self
+                .options
+                .variables
+                .iter()
+                .any(|(defined, _)| *defined == name)
+            {
+                self.variables.insert(name, value);
+            }
+
+        // "#enter-shell:" and "#exit-shell" surround commands run in a nested
+        // shell, typed after its prompt
+        } else if let Some(stripped) = line.strip_prefix("#enter-shell:") {
+            if stripped.trim().is_empty() {
+                return Err(format_err!(
+                    "`#enter-shell:` on line {} needs a prompt",
+                    location
+                ));
+            }
+            self.shells.push(format!("{} ", stripped.trim()));
+        } else if line.trim_end() == "#exit-shell" {
+            if self.shells.pop().is_none() {
+                return Err(format_err!(
+                    "`#exit-shell` on line {} without `#enter-shell:`",
+                    location
+                ));
+            }
+
+        // lines starting with "#entry:" insert a raw asciicast entry
+        } else if let Some(stripped) = line.strip_prefix("#entry:") {
+            let mut entry = parse_entry(stripped).map_err(at_line(&location))?;
+            if self.options.rebase {
+                check_timeout(self.header.max_timeout, "#entry:", entry.time)
+                    .map_err(at_line(&location))?;
+                self.time = self.time.saturating_add(micros(entry.time));
+                entry.time = seconds(self.time);
+            }
+            self.entries.push(entry);
+
+        // lines starting with "#echo:" print the rest of the line as is
+        } else if let Some(stripped) = line.strip_prefix("#echo:") {
+            let line = stripped.strip_prefix(' ').unwrap_or(stripped);
+            self.output(line.to_string(), true);
+
+        // lines starting with "#marker:" add a marker players can jump to
+        } else if let Some(stripped) = line.strip_prefix("#marker:") {
+            self.entries.push(Entry {
+                time: seconds(self.time),
+                event_type: EventType::Marker,
+                event_data: stripped.trim().to_string(),
+            });
+
+        // lines starting with "#resize:" resize the terminal
+        } else if let Some(stripped) = line.strip_prefix("#resize:") {
+            self.size = parse_size(stripped).map_err(at_line(&location))?;
+            self.entries.push(resize_entry(self.time, self.size));
+            self.preview_lines.push(PreviewLine::Resize {
+                width: self.size.0,
+                height: self.size.1,
+            });
+
+        // lines starting with "#embed:" insert the events of a recorded cast,
+        // the time moves on to its last event
+        } else if let Some(stripped) = line.strip_prefix("#embed:") {
+            let (path, resize) = embed_arguments(stripped);
+            let (cast_size, cast_entries) =
+                read_cast(Path::new(path)).map_err(at_line(&location))?;
+            if let Some(last) = cast_entries.last() {
+                check_timeout(self.header.max_timeout, "#embed:", last.time)
+                    .map_err(at_line(&location))?;
+            }
+            let resize = resize && cast_size != self.size;
+            if resize {
+                self.entries.push(resize_entry(self.time, cast_size));
+                self.preview_lines.push(PreviewLine::Resize {
+                    width: cast_size.0,
+                    height: cast_size.1,
+                });
+            }
+            let start = self.time;
+            for entry in cast_entries {
+                self.time = start.saturating_add(micros(entry.time));
+                self.entries.push(Entry {
+                    time: seconds(self.time),
+                    ..entry
+                });
+            }
+            // the terminal gets back to its size after the cast
+            if resize {
+                self.entries.push(resize_entry(self.time, self.size));
+                self.preview_lines.push(PreviewLine::Resize {
+                    width: self.size.0,
+                    height: self.size.1,
+                });
+            }
+
+        // lines starting with "#caption:" or "#note:" add a caption
+        } else if let Some(stripped) = line
+            .strip_prefix("#caption:")
+            .or_else(|| line.strip_prefix("#note:"))
+        {
+            self.captions.push((self.time, stripped.trim().to_string()));
+
+        // lines starting with "#hint:" show a hint over the animated SVG
+        // preview, the cast is left as is
+        } else if let Some(stripped) = line.strip_prefix("#hint:") {
+            if stripped.trim().is_empty() {
+                return Err(format_err!("`#hint:` on line {} needs a text", location));
+            }
+            self.hints.push((self.time, stripped.trim().to_string()));
+
+        // output lines between "#shuffle-begin" and "#shuffle-end" are
+        // printed in a random order, as if written by concurrent processes
+        } else if line.trim_end() == "#shuffle-begin" {
+            if let Some((begin, _)) = &self.shuffle {
+                return Err(format_err!(
+                    "`#shuffle-begin` on line {} inside the block opened on line {}",
+                    location,
+                    begin
+                ));
+            }
+            self.shuffle = Some((location.clone(), vec![]));
+        } else if line.trim_end() == "#shuffle-end" {
+            let (_, mut lines) = self.shuffle.take().ok_or_else(|| {
+                format_err!("`#shuffle-end` on line {} without a block", location)
+            })?;
+            self.typing.rng.shuffle(&mut lines);
+            for line in lines {
+                let line = markup(&line);
+                self.time = self
+                    .time
+                    .saturating_add(micros(self.typing.rng.f64() * self.step));
+                self.entries.push(Entry {
+                    time: seconds(self.time),
+                    event_type: EventType::Output,
+                    event_data: format!("{}{}", line, self.header.newline.as_str()),
+                });
+                self.preview_lines.push(PreviewLine::Output(line));
+            }
+
+        // "#toc" displays a table of contents, rendered once all chapters
+        // are known
+        } else if line.trim_end() == "#toc" {
+            if self.options.split {
+                return Err(format_err!(
+                    "`#toc` on line {} is not supported with --split-on-clear",
+                    location
+                ));
+            }
+            self.tocs.push(Toc {
+                entry: self.entries.len(),
+                preview_line: self.preview_lines.len(),
+                time: self.time,
+            });
+
+        // lines starting with "#chapter:" start a new (optionally named) chapter
+        } else if let Some(stripped) = line.strip_prefix("#chapter:") {
+            let label = Some(stripped.trim().to_string()).filter(|label| !label.is_empty());
+            if self.options.split {
+                split(
+                    &mut self.clips,
+                    &mut self.clip_label,
+                    &mut self.entries,
+                    label,
+                );
+                self.time = self.header.start_time();
+                self.cursor = true;
+            } else {
+                clear_terminal(&mut self.entries, &mut self.time, &self.step, &self.header)?;
+            }
+            self.preview_lines.push(PreviewLine::Clear);
+            if !stripped.trim().is_empty() {
+                self.chapters.push((stripped.trim().to_string(), self.time));
+            }
+
+        // lines starting with "#>" are typed after a prompt as a shell
+        // comment, eg. to explain the next step on screen
+        } else if let Some(stripped) = line.strip_prefix("#>") {
+            let comment = format!("# {}", stripped.trim_start())
+                .trim_end()
+                .to_string();
+            self.preview_lines.push(
+                match self.shells.last() {
+                    Some(prompt) => echo_shell_line(
+                        &mut self.entries,
+                        &mut self.time,
+                        &self.step,
+                        &mut self.typing,
+                        &" ".repeat(self.shells.len() * self.header.shell_indent),
+                        prompt,
+                        &comment,
+                    ),
+                    None => echo_console_line(
+                        &mut self.entries,
+                        &mut self.time,
+                        &self.step,
+                        &mut self.typing,
+                        &self.header,
+                        "",
+                        &comment,
+                    ),
+                }
+                .map_err(at_line(&location))?,
+            );
+
+        // skip lines starting with "#"
+        } else if line.starts_with('#') {
+            if let Some(directive) = unknown_directive(&line) {
+                if self.options.check || self.options.strict {
+                    return Err(format_err!(
+                        "line {}: unknown directive `{}`",
+                        location,
+                        directive
+                    ));
+                }
+                warn!(
+                    "ignoring unknown directive `{}` on line {}",
+                    directive, location
+                );
+            }
+            return Ok(());
+
+        // lines starting with "$! " print the command at once, as if pasted
+        } else if let Some(stripped) = line.strip_prefix("$! ") {
+            // nested shells have no right prompt
+            let (prompt, rprompt) = match self.shells.last() {
+                Some(prompt) => (
+                    format!(
+                        "{}{}",
+                        " ".repeat(self.shells.len() * self.header.shell_indent),
+                        prompt
+                    ),
+                    None,
+                ),
+                None => (self.header.prompt.clone(), self.header.rprompt.clone()),
+            };
+            self.commands += 1;
+            self.preview_lines.push(
+                echo_pasted_line(
+                    &mut self.entries,
+                    &mut self.time,
+                    &self.step,
+                    &self.header,
+                    &prompt,
+                    &rprompt,
+                    stripped,
+                )
+                .map_err(at_line(&location))?,
+            );
+
+        // lines starting with "$ " in a nested shell use its prompt
+        } else if let (Some(stripped), Some(prompt)) = (line.strip_prefix("$ "), self.shells.last())
+        {
+            let indent = " ".repeat(self.shells.len() * self.header.shell_indent);
+            self.commands += 1;
+            self.preview_lines.push(
+                echo_shell_line(
+                    &mut self.entries,
+                    &mut self.time,
+                    &self.step,
+                    &mut self.typing,
+                    &indent,
+                    prompt,
+                    stripped,
+                )
+                .map_err(at_line(&location))?,
+            );
+
+        // lines starting with "$ " display as console lines
+        } else if let Some(stripped) = line.strip_prefix("$ ") {
+            self.console_line("", stripped)
+                .map_err(at_line(&location))?;
+
+        // lines starting with a prompt prefix (eg. "(nix-shell) $ ") display
+        // as console lines with the prefix
+        } else if let Some((prefix, stripped)) =
+            self.header
+                .prompt_prefixes
+                .iter()
+                .find_map(|prompt_prefix| {
+                    line.strip_prefix(prompt_prefix.as_str()).map(|stripped| {
+                        (
+                            prompt_prefix
+                                .strip_suffix("$ ")
+                                .unwrap_or(prompt_prefix)
+                                .to_string(),
+                            stripped,
+                        )
+                    })
+                })
+        {
+            self.console_line(&prefix, stripped)
+                .map_err(at_line(&location))?;
+
+        // lines starting with "--" will clear display, and the preview
+        } else if line.starts_with("--") {
+            self.clear()?;
+
+        // timeout
+        } else if line.trim() == "" {
+            self.time = self
+                .time
+                .saturating_add(micros(self.header.blank_pause * self.step));
+
+        // lines starting with "> " print their output as if streamed by a
+        // program
+        } else if let Some(stripped) = line.strip_prefix("> ") {
+            let line = format!(
+                "{}{}",
+                " ".repeat(self.shells.len() * self.header.shell_indent),
+                stripped
+            );
+            let output_step = self.header.output_step.unwrap_or(self.step / 4.0);
+            self.preview_lines.push(stream_output_line(
+                &mut self.entries,
+                &mut self.time,
+                output_step,
+                self.header.newline,
+                &line,
+            ));
+
+        // output of a shuffle block is printed at its end
+        } else if let Some((_, lines)) = self.shuffle.as_mut() {
+            lines.push(line);
+
+        // everything else print immediately, "\#" at the start prints a "#"
+        // and "\c" at the end leaves out the newline
+        } else {
+            let line = line
+                .strip_prefix('\\')
+                .filter(|line| line.starts_with('#'))
+                .unwrap_or(&line);
+            let (line, newline) = strip_newline_marker(line);
+            let line = format!(
+                "{}{}",
+                " ".repeat(self.shells.len() * self.header.shell_indent),
+                markup(line)
+            );
+            self.output(line, newline);
+        }
+        Ok(())
+    }
+
+    /// Type `command` after the prompt (with its `prefix`, eg.
+    /// `(nix-shell) `), like a `$ ` line.
+    pub(crate) fn console_line(&mut self, prefix: &str, command: &str) -> Result<(), Error> {
+        self.commands += 1;
+        let preview_line = echo_console_line(
+            &mut self.entries,
+            &mut self.time,
+            &self.step,
+            &mut self.typing,
+            &self.header,
+            prefix,
+            command,
+        )?;
+        self.preview_lines.push(preview_line);
+        Ok(())
+    }
+
+    /// Print `line` as is, ended by a newline unless `newline` is false.
+    pub(crate) fn output(&mut self, line: String, newline: bool) {
+        self.entries.push(Entry {
+            time: seconds(self.time),
+            event_type: EventType::Output,
+            event_data: if newline {
+                format!("{}{}", line, self.header.newline.as_str())
+            } else {
+                line.clone()
+            },
+        });
+        self.preview_lines
+            .push(PreviewLine::Output(line).terminated(newline));
+    }
+
+    /// Clear the screen like a `--` line, or start a new clip when
+    /// splitting.
+    pub(crate) fn clear(&mut self) -> Result<(), Error> {
+        if self.options.split {
+            split(
+                &mut self.clips,
+                &mut self.clip_label,
+                &mut self.entries,
+                None,
+            );
+            self.time = self.header.start_time();
+            self.cursor = true;
+        } else {
+            clear_terminal(&mut self.entries, &mut self.time, &self.step, &self.header)?;
+        }
+        self.preview_lines.push(PreviewLine::Clear);
+        Ok(())
+    }
+
+    /// Wait for `duration` seconds, like a `#timeout:` line.
+    pub(crate) fn timeout(&mut self, duration: f64) -> Result<(), Error> {
+        check_timeout(self.header.max_timeout, "#timeout:", duration)?;
+        self.time = self.time.saturating_add(micros(duration));
+        Ok(())
+    }
+
+    /// Finish rendering the scenario, which `included` these files.
+    /// Hand out the entries rendered so far, once scaled to the speed, when
+    /// nothing left in the scenario can change them (see [`stream`]): not
+    /// when splitting into clips, with an `idle_time_limit` or after a
+    /// `#toc` line.
+    pub(crate) fn final_entries(&mut self) -> Vec<Entry> {
+        if self.options.split || self.header.idle_time_limit.is_some() || !self.tocs.is_empty() {
+            return vec![];
+        }
+        let speed = self.options.speed;
+        let entries: Vec<Entry> = self
+            .entries
+            .drain(..)
+            .map(|entry| Entry {
+                time: seconds(scaled(micros(entry.time), speed)),
+                ..entry
+            })
+            .collect();
+        self.markers.extend(
+            entries
+                .iter()
+                .filter(|entry| entry.event_type == EventType::Marker)
+                .map(|entry| (entry.event_data.clone(), micros(entry.time))),
+        );
+        self.streamed += entries.len();
+        entries
+    }
+
+    pub(crate) fn finish(self, included: Vec<PathBuf>) -> Result<Scenario, Error> {
+        let Renderer {
+            options,
+            header,
+            mut entries,
+            mut preview_lines,
+            mut preview_times,
+            mut clips,
+            mut clip_label,
+            mut time,
+            assertions,
+            mut layout,
+            mut chapters,
+            mut tocs,
+            markers,
+            streamed,
+            mut captions,
+            mut hints,
+            shuffle,
+            commands,
+            size,
+            cursor,
+            ..
+        } = self;
+
+        preview_times.resize(preview_lines.len(), time);
+        // the cursor is shown again at the end
+        if !cursor {
+            entries.push(cursor_entry(time, true));
+        }
+
+        if let Some((begin, _)) = shuffle {
+            return Err(format_err!(
+                "`#shuffle-begin` on line {} is never closed by `#shuffle-end`",
+                begin
+            ));
+        }
+
+        if let Some(limit) = header.idle_time_limit {
+            let limit = micros(limit);
+            for clip in clips.iter_mut() {
+                limit_idle_time(&mut clip.entries, limit);
+            }
+            let moves = limit_idle_time(&mut entries, limit);
+            for (_, chapter_time) in chapters.iter_mut() {
+                *chapter_time = moved(&moves, limit, *chapter_time);
+            }
+            for (caption_time, _) in captions.iter_mut().chain(hints.iter_mut()) {
+                *caption_time = moved(&moves, limit, *caption_time);
+            }
+            for toc in tocs.iter_mut() {
+                toc.time = moved(&moves, limit, toc.time);
+            }
+            for preview_time in preview_times.iter_mut() {
+                *preview_time = moved(&moves, limit, *preview_time);
+            }
+            time = moved(&moves, limit, time);
+        }
+
+        if options.speed != 1.0 {
+            let scale = |time: Micros| scaled(time, options.speed);
+            for entry in clips
+                .iter_mut()
+                .flat_map(|clip| clip.entries.iter_mut())
+                .chain(entries.iter_mut())
+            {
+                entry.time = seconds(scale(micros(entry.time)));
+            }
+            for (_, chapter_time) in chapters.iter_mut() {
+                *chapter_time = scale(*chapter_time);
+            }
+            for (caption_time, _) in captions.iter_mut().chain(hints.iter_mut()) {
+                *caption_time = scale(*caption_time);
+            }
+            for toc in tocs.iter_mut() {
+                toc.time = scale(toc.time);
+            }
+            for preview_time in preview_times.iter_mut() {
+                *preview_time = scale(*preview_time);
+            }
+            time = scale(time);
+        }
+
+        // second pass: insert the tables of contents, last one first so that
+        // the recorded positions stay valid
+        let mut contents = chapters.clone();
+        contents.extend(markers);
+        contents.extend(
+            entries
+                .iter()
+                .filter(|entry| entry.event_type == EventType::Marker)
+                .map(|entry| (entry.event_data.clone(), micros(entry.time))),
+        );
+        contents.sort_by_key(|(_, time)| *time);
+        for toc in tocs.iter().rev() {
+            let lines = toc_lines(&contents);
+            for (offset, (output, preview_line)) in lines.into_iter().enumerate() {
+                entries.insert(
+                    toc.entry + offset,
+                    Entry {
+                        time: seconds(toc.time),
+                        event_type: EventType::Output,
+                        event_data: format!("{}{}", output, header.newline.as_str()),
+                    },
+                );
+                preview_lines.insert(toc.preview_line + offset, preview_line);
+                preview_times.insert(toc.preview_line + offset, toc.time);
+            }
+        }
+
+        for preview_line in &preview_lines {
+            layout.add(preview_line, header.tabstop);
+        }
+        if !options.preview {
+            preview_lines.clear();
+            preview_times.clear();
+        }
+        check_assertions(&assertions, &layout, options.check || options.strict)?;
+
+        // a cast with only a header is most likely a mistake, eg. an empty file
+        if streamed == 0 && entries.is_empty() && clips.iter().all(|clip| clip.entries.is_empty()) {
+            if options.strict {
+                return Err(format_err!("scenario produced no events"));
+            }
+            warn!("scenario produced no events");
+        }
+
+        if options.split {
+            split(&mut clips, &mut clip_label, &mut entries, None);
+        }
+
+        Ok(Scenario {
+            header,
+            entries,
+            clips,
+            preview_lines,
+            preview_times,
+            chapters,
+            captions,
+            hints,
+            commands,
+            size,
+            end: time,
+            included,
+        })
+    }
+}
+
+impl Scenario {
+    /// Round the times of the entries (and clips) to the precision of the
+    /// cast like [`print_cast`] does, the chapters, captions, hints, preview
+    /// times and end move along with the entries before them and are rounded
+    /// too. Everything then matches the times of the printed cast.
+    pub fn round_times(&mut self, options: &OutputOptions) {
+        let times = output_times(&self.entries, options);
+        // how much each entry moves, in the order of the entries
+        let shifts: Vec<(Micros, f64)> = self
+            .entries
+            .iter()
+            .zip(&times)
+            .map(|(entry, time)| (micros(entry.time), time - entry.time))
+            .collect();
+        let shifted = |time: Micros| {
+            let shift = match shifts.partition_point(|(entry_time, _)| *entry_time <= time) {
+                0 => 0.0,
+                index => shifts[index - 1].1,
+            };
+            micros(output_time((seconds(time) + shift).max(0.0), options))
+        };
+        for (_, chapter_time) in self.chapters.iter_mut() {
+            *chapter_time = shifted(*chapter_time);
+        }
+        for (caption_time, _) in self.captions.iter_mut().chain(self.hints.iter_mut()) {
+            *caption_time = shifted(*caption_time);
+        }
+        for preview_time in self.preview_times.iter_mut() {
+            *preview_time = shifted(*preview_time);
+        }
+        self.end = shifted(self.end);
+        for (entry, time) in self.entries.iter_mut().zip(times) {
+            entry.time = time;
+        }
+        for clip in self.clips.iter_mut() {
+            let times = output_times(&clip.entries, options);
+            for (entry, time) in clip.entries.iter_mut().zip(times) {
+                entry.time = time;
+            }
+        }
+    }
+}
+
+impl ScenarioHeader {
+    /// Width of a cell of the SVG preview, in pixels, a monospace glyph is
+    /// about 0.6 times as wide as the font size.
+    pub fn svg_cell_width(&self) -> u32 {
+        (f64::from(self.font_size) * 0.6).round() as u32
+    }
+
+    /// Time of the first event: `initial_offset` steps after `start_delay`.
+    pub fn start_time(&self) -> Micros {
+        micros(self.start_delay + self.initial_offset * self.step)
+    }
+
+    /// Height of a line of the SVG preview, in pixels.
+    pub fn svg_line_height(&self) -> u32 {
+        (f64::from(self.font_size) * 1.2).round() as u32
+    }
+}
+
+/// Wrap the spans of a line into rows of `width` columns, like a terminal
+/// does.
+fn wrap(
+    spans: Vec<(Option<String>, String)>,
+    width: usize,
+    tabstop: usize,
+) -> Vec<Vec<(Option<String>, String)>> {
+    let mut rows = vec![vec![]];
+    let mut column = 0;
+    for (class, text) in spans {
+        for grapheme in text.graphemes(true) {
+            let mut cells = cells_at(grapheme, column, tabstop);
+            // a tab is expanded into spaces, it never moves past the last column
+            let spaces;
+            let grapheme = if grapheme == "\t" {
+                if width > 0 {
+                    cells = cells.min(width.saturating_sub(column).max(1));
+                }
+                spaces = " ".repeat(cells);
+                &spaces
+            } else {
+                grapheme
+            };
+            if width > 0 && column + cells > width {
+                rows.push(vec![]);
+                column = 0;
+            }
+            let row: &mut Vec<(Option<String>, String)> = rows.last_mut().unwrap();
+            match row.last_mut() {
+                Some((last_class, last_text)) if *last_class == class => {
+                    last_text.push_str(grapheme)
+                }
+                _ => row.push((class.clone(), grapheme.to_string())),
+            }
+            column += cells;
+        }
+    }
+    rows
+}
+
+/// Cut the spans of a line longer than `width` columns at `width - 1`
+/// columns and end it with `…`, instead of wrapping it (see
+/// `preview_truncate` in the header).
+fn truncate(
+    spans: Vec<(Option<String>, String)>,
+    width: usize,
+    tabstop: usize,
+) -> Vec<Vec<(Option<String>, String)>> {
+    let rows = wrap(spans.clone(), width, tabstop);
+    if rows.len() < 2 {
+        return rows;
+    }
+    let mut row = wrap(spans, width.saturating_sub(1).max(1), tabstop).swap_remove(0);
+    row.push((None, "…".to_string()));
+    vec![row]
+}
+
+/// A row of the SVG preview, as spans of text each with an optional CSS
+/// class.
+struct PreviewRow {
+    spans: Vec<(Option<String>, String)>,
+    /// when the row is shown
+    time: Micros,
+    /// the number of the screen (between two clears) the row is on
+    screen: usize,
+}
+
+/// Turn the preview lines, with the time they are shown at, into rows: lines
+/// longer than the width of the terminal wrap (or are truncated) and
+/// unterminated lines are joined to the next one.
+fn preview_rows(
+    header: &ScenarioHeader,
+    preview_lines: impl IntoIterator<Item = (PreviewLine, Micros)>,
+) -> Vec<PreviewRow> {
+    let fit = if header.preview_truncate {
+        truncate
+    } else {
+        wrap
+    };
+    let mut columns = header.width;
+    let mut screen = 0;
+    let mut rows = vec![];
+    // spans of the unterminated lines the next line continues
+    let mut row = vec![];
+    let mut time = 0;
+    for (preview_line, line_time) in preview_lines {
+        time = line_time;
+        let spans = match preview_line {
+            PreviewLine::Resize { width, .. } => {
+                columns = width;
+                continue;
+            }
+            PreviewLine::Clear => {
+                screen += 1;
+                continue;
+            }
+            PreviewLine::Unterminated(preview_line) => {
+                row.extend(preview_line.spans(header, columns as usize));
+                continue;
+            }
+            preview_line => preview_line.spans(header, columns as usize),
+        };
+        row.extend(spans);
+        rows.extend(
+            fit(std::mem::take(&mut row), columns as usize, header.tabstop)
+                .into_iter()
+                .map(|spans| PreviewRow {
+                    spans,
+                    time,
+                    screen,
+                }),
+        );
+    }
+    if !row.is_empty() {
+        rows.extend(
+            fit(row, columns as usize, header.tabstop)
+                .into_iter()
+                .map(|spans| PreviewRow {
+                    spans,
+                    time,
+                    screen,
+                }),
+        );
+    }
+    rows
+}
+
+/// The spans of a row of the SVG preview as a `<tspan>`.
+fn row_tspan(spans: Vec<(Option<String>, String)>) -> TSpan {
+    let mut tspan = TSpan::new().set("x", "0");
+    for (class, text) in spans {
+        tspan = match class {
+            Some(class) => tspan.append(
+                TSpan::new()
+                    .set("class", class)
+                    .append(Text::new(encode_safe(&text))),
+            ),
+            None => tspan.append(Text::new(encode_safe(&text))),
+        };
+    }
+    tspan
+}
+
+/// An SVG image of the terminal `header` describes, with `text` on it.
+fn svg_document(header: &ScenarioHeader, text: TextElement) -> Document {
+    let width = header.width * header.svg_cell_width();
+    let height = header.height * header.svg_line_height();
+    let mask_rect = Rectangle::new()
+        .set("x", "0")
+        .set("y", "0")
+        .set("width", width)
+        .set("height", height)
+        .set("fill", "#fff");
+    let mask = Mask::new().set("id", "bigterminal-mask").add(mask_rect);
+    let rect = Rectangle::new()
+        .set("class", "background")
+        .set("y", "0")
+        .set("x", "0")
+        .set("width", width)
+        .set("height", height);
+
+    Document::new()
+        .set("xmlns:dc", "http://purl.org/dc/elements/1.1/")
+        .set("xmlns:cc", "http://creativecommons.org/ns#")
+        .set("xmlns:rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#")
+        .set("xmlns:svg", "http://www.w3.org/2000/svg")
+        .set("xmlns", "http://www.w3.org/2000/svg")
+        .set("version", "1.1")
+        .set("width", "100%")
+        .set("viewBox", format!("0 0 {} {}", width, height))
+        .set("preserveAspectRatio", "xMidYMid meet")
+        // the theme is checked by `render`
+        .add(Style::new(svg_style(
+            theme(&header.theme).unwrap_or(&THEMES[0]),
+        )))
+        .add(mask)
+        .add(rect)
+        .add(text)
+}
+
+/// The `<text>` element the rows of the SVG preview are added to.
+fn svg_text(header: &ScenarioHeader) -> TextElement {
+    TextElement::new()
+        .set("transform", "translate(0 0)")
+        .set("y", "0")
+        .set("x", "0")
+        .set("font-family", header.font_family.as_str())
+        .set("font-size", header.font_size)
+        .set("xml:space", "preserve")
+}
+
+/// Draw the preview lines as an SVG image of the terminal at the end of the
+/// cast: only the lines after the last clear are shown, and only the last
+/// `height` rows of them since the terminal scrolls. Lines longer than the
+/// width of the terminal wrap.
+pub fn svg_preview(header: &ScenarioHeader, preview_lines: Vec<PreviewLine>) -> Document {
+    let mut text = svg_text(header).set("mask", "url(#bigterminal-mask)");
+    let screen = preview_lines
+        .iter()
+        .filter(|preview_line| matches!(preview_line, PreviewLine::Clear))
+        .count();
+    let rows: Vec<PreviewRow> =
+        preview_rows(header, preview_lines.into_iter().map(|line| (line, 0)))
+            .into_iter()
+            .filter(|row| row.screen == screen)
+            .collect();
+    let scrolled = rows.len().saturating_sub(header.height as usize);
+    for row in rows.into_iter().skip(scrolled) {
+        text = text.add(row_tspan(row.spans).set("dy", header.svg_line_height()));
+    }
+    svg_document(header, text)
+}
+
+/// How long the last frame of the animated SVG preview stays visible before
+/// it plays again, in seconds.
+const SVG_FINAL_FRAME_DURATION: f64 = 2.0;
+
+/// An SMIL animation of `attribute` looping over `duration`, taking each of
+/// the `values` from its time on.
+fn svg_animation(
+    tag: &str,
+    attribute: &str,
+    values: &[(Micros, String)],
+    duration: f64,
+) -> Element {
+    let key_times = values
+        .iter()
+        .map(|(time, _)| format!("{:.4}", seconds(*time) / duration))
+        .collect::<Vec<_>>();
+    let values = values
+        .iter()
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+    let mut animation = Element::new(tag);
+    animation.assign("attributeName", attribute);
+    animation.assign("values", values.join(";"));
+    animation.assign("keyTimes", key_times.join(";"));
+    animation.assign("dur", format!("{}s", duration));
+    animation.assign("calcMode", "discrete");
+    animation.assign("repeatCount", "indefinite");
+    animation
+}
+
+/// How long a `#hint:` takes to fade out of the animated SVG preview, in
+/// seconds.
+const SVG_HINT_FADE_DURATION: f64 = 0.5;
+
+/// A `#hint:` box over the bottom right corner of the animated SVG preview,
+/// in the colors of the theme. It shows up at `time`, stays for
+/// `hint_duration` seconds then fades out.
+fn svg_hint(header: &ScenarioHeader, time: Micros, hint: &str, duration: f64) -> Group {
+    let cell_width = header.svg_cell_width();
+    let line_height = header.svg_line_height();
+    let padding = cell_width;
+    let width = columns(hint, header.tabstop) as u32 * cell_width + 2 * padding;
+    let height = line_height + padding;
+    let x = (header.width * cell_width).saturating_sub(width + padding);
+    let y = (header.height * line_height).saturating_sub(height + padding);
+
+    let end = micros(duration);
+    let shown = time.saturating_add(micros(header.hint_duration)).min(end);
+    let faded = shown
+        .saturating_add(micros(SVG_HINT_FADE_DURATION))
+        .min(end);
+    let opacity: Vec<(Micros, String)> = [
+        (0, "0"),
+        (time, "0"),
+        (time, "1"),
+        (shown, "1"),
+        (faded, "0"),
+        (end, "0"),
+    ]
+    .iter()
+    .map(|(time, value)| (*time, value.to_string()))
+    .collect();
+    let mut fade = svg_animation("animate", "opacity", &opacity, duration);
+    fade.assign("calcMode", "linear");
+
+    Group::new()
+        .set("class", "hint")
+        .set("opacity", "0")
+        .add(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", width)
+                .set("height", height)
+                .set("rx", padding / 2),
+        )
+        .add(
+            TextElement::new()
+                .set("x", x + padding)
+                .set("y", y + line_height)
+                .set("font-family", header.font_family.as_str())
+                .set("font-size", header.font_size)
+                .add(Text::new(encode_safe(hint))),
+        )
+        .add(fade)
+}
+
+/// Draw the preview lines as an animated SVG image playing like the cast:
+/// every line shows up at `preview_times`, the screen is cleared by `--` and
+/// `#chapter:` and scrolls once it is full. The `hints` show up over it at
+/// their time. It loops after `end`, or once the last hint faded out.
+pub fn animated_svg_preview(
+    header: &ScenarioHeader,
+    preview_lines: Vec<PreviewLine>,
+    preview_times: &[Micros],
+    hints: &[(Micros, String)],
+    end: Micros,
+) -> Document {
+    let end = hints
+        .iter()
+        .map(|(time, _)| time.saturating_add(micros(header.hint_duration + SVG_HINT_FADE_DURATION)))
+        .fold(end, Micros::max);
+    let duration = seconds(end) + SVG_FINAL_FRAME_DURATION;
+    // when every screen (but the first one) starts
+    let clears: Vec<Micros> = preview_lines
+        .iter()
+        .zip(preview_times)
+        .filter(|(preview_line, _)| matches!(preview_line, PreviewLine::Clear))
+        .map(|(_, time)| *time)
+        .collect();
+    let rows = preview_rows(
+        header,
+        preview_lines.into_iter().zip(preview_times.iter().copied()),
+    );
+
+    // the text is not masked, the mask would scroll with it
+    let mut text = svg_text(header);
+    // vertical offset of the text, the screen scrolls up once it is full
+    let mut offsets: Vec<(Micros, u32)> = vec![(0, 0)];
+    let mut screen_row: u32 = 0;
+    let mut screen = 0;
+    for row in rows {
+        if row.screen != screen {
+            screen = row.screen;
+            screen_row = 0;
+            offsets.push((clears[screen - 1], 0));
+        }
+        screen_row += 1;
+        let offset = screen_row.saturating_sub(header.height) * header.svg_line_height();
+        offsets.push((row.time, offset));
+
+        let mut visibility = vec![(0, "hidden".to_string()), (row.time, "visible".to_string())];
+        if let Some(clear) = clears.get(row.screen) {
+            visibility.push((*clear, "hidden".to_string()));
+        }
+        text = text.add(
+            row_tspan(row.spans)
+                .set("y", screen_row * header.svg_line_height())
+                .set("visibility", "hidden")
+                .append(svg_animation(
+                    "animate",
+                    "visibility",
+                    &visibility,
+                    duration,
+                )),
+        );
+    }
+    // only the last offset at a time counts, and only the changes of offset
+    offsets.reverse();
+    offsets.dedup_by_key(|(time, _)| *time);
+    offsets.reverse();
+    offsets.dedup_by_key(|(_, offset)| *offset);
+    let offsets: Vec<(Micros, String)> = offsets
+        .into_iter()
+        .map(|(time, offset)| (time, format!("0 {}", -i64::from(offset))))
+        .collect();
+    let mut scroll = svg_animation("animateTransform", "transform", &offsets, duration);
+    scroll.assign("type", "translate");
+    hints.iter().fold(
+        svg_document(header, text.add(scroll)),
+        |document, (time, hint)| document.add(svg_hint(header, *time, hint, duration)),
+    )
+}
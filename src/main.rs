@@ -1,214 +1,133 @@
-use asciicast::{Entry, EventType, Header};
-use failure::Error;
-use html_escape::encode_safe;
-use serde::Deserialize;
-use serde_json::{from_str, to_string};
+use asciinema_scenario::{
+    animated_svg_preview, included_files, merge_entries, parse_header, parse_variable, print_cast,
+    read_header, render, stream, strip_final_newline, svg_preview, validate_cast, write_captions,
+    CastFormat, Clips, InputFormat, OutputOptions, RenderOptions, Summary, TimeUnits, ALIASES,
+    DIRECTIVES,
+};
+use failure::{format_err, Error};
+use serde_json::to_string;
+use sha2::{Digest, Sha256};
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
-use std::collections::hash_map::DefaultHasher;
-use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::env::var_os;
+use std::fs::{create_dir_all, read, File};
+use std::io::{stdin, stdout, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::from_utf8;
+#[cfg(feature = "watch")]
+use std::sync::mpsc::channel;
+#[cfg(feature = "watch")]
+use std::time::{Duration, Instant};
+use std::time::{SystemTime, UNIX_EPOCH};
 use structopt::StructOpt;
-use structopt_flags::{LogLevel, Verbose};
-use svg::node::element::{Element, Mask, Rectangle, Text as TextElement};
-use svg::node::{NodeDefaultHash, Text, Value};
-use svg::{Document, Node};
-
-const TSPAN_TAG: &str = "tspan";
-
-#[derive(Clone, Debug)]
-pub struct TSpan {
-    inner: Element,
-}
-
-impl TSpan {
-    pub fn new() -> Self {
-        TSpan {
-            inner: Element::new(TSPAN_TAG),
+use structopt_flags::{LogLevel, QuietVerbose};
+
+#[cfg(feature = "gif")]
+use asciinema_scenario::gif;
+#[cfg(feature = "png")]
+use asciinema_scenario::png;
+#[cfg(feature = "watch")]
+use log::info;
+#[cfg(feature = "watch")]
+use notify::{RecursiveMode, Watcher};
+
+fn list_directives() {
+    for (directive, description) in DIRECTIVES {
+        let aliases: Vec<&str> = ALIASES
+            .iter()
+            .filter(|(_, canonical)| canonical == directive)
+            .map(|(alias, _)| *alias)
+            .collect();
+        if aliases.is_empty() {
+            println!("{:<20} {}", directive, description);
+        } else {
+            println!(
+                "{:<20} {} (alias: {})",
+                directive,
+                description,
+                aliases.join(", ")
+            );
         }
     }
-
-    pub fn append<T>(mut self, node: T) -> Self
-    where
-        T: Node,
-    {
-        Node::append(&mut self, node);
-        self
-    }
-
-    #[inline]
-    pub fn set<T, U>(mut self, name: T, value: U) -> Self
-    where
-        T: Into<String>,
-        U: Into<Value>,
-    {
-        Node::assign(&mut self, name, value);
-        self
-    }
-
-    #[inline]
-    pub fn get_inner(&self) -> &Element {
-        &self.inner
-    }
 }
 
-impl Default for TSpan {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+/// Seed of the random number generator with `--deterministic`.
+const DETERMINISTIC_SEED: u64 = 0;
 
-impl NodeDefaultHash for TSpan {
-    fn default_hash(&self, state: &mut DefaultHasher) {
-        self.inner.default_hash(state);
-    }
+/// Whether the messages of the tool are colored, unless `--no-color` is given
+/// or `NO_COLOR` is set (see <https://no-color.org>).
+fn colored(cli: &Cli) -> bool {
+    !cli.no_color && var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
 }
 
-impl Node for TSpan {
-    fn append<T>(&mut self, node: T)
-    where
-        T: Node,
-    {
-        self.inner.append(node);
-    }
-
-    fn assign<T, U>(&mut self, name: T, value: U)
-    where
-        T: Into<String>,
-        U: Into<Value>,
-    {
-        self.inner.assign(name, value);
+/// The `ERROR:` label of the messages of the tool.
+fn error_label(cli: &Cli) -> &'static str {
+    if colored(cli) {
+        "\x1b[31mERROR:\x1b[0m"
+    } else {
+        "ERROR:"
     }
 }
 
-impl Display for TSpan {
-    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        self.inner.fmt(formatter)
+/// The `OK:` label of the messages of the tool.
+fn ok_label(cli: &Cli) -> &'static str {
+    if colored(cli) {
+        "\x1b[32mOK:\x1b[0m"
+    } else {
+        "OK:"
     }
 }
 
-impl From<TSpan> for Element {
-    fn from(val: TSpan) -> Self {
-        val.inner
+/// A stable SHA-256 hash of everything the generated cast depends on: the
+/// version of asciinema-scenario, the scenario, the files it `included`, the
+/// file given to `--merge-entries` and the options changing the output.
+fn scenario_hash(cli: &Cli, input: &[u8], included: &[PathBuf]) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(input);
+    hasher.update([0]);
+    for path in included {
+        hasher.update(read(path)?);
+        hasher.update([0]);
     }
-}
-
-#[derive(Deserialize, Debug)]
-struct ScenarioHeader {
-    #[serde(default = "default_step")]
-    step: f64,
-
-    #[serde(default = "default_width")]
-    width: u32,
-
-    #[serde(default = "default_height")]
-    height: u32,
-}
-
-fn default_step() -> f64 {
-    0.10
-}
-
-fn default_width() -> u32 {
-    77
-}
-
-fn default_height() -> u32 {
-    20
-}
-
-fn print_entry(entry: Entry) -> Result<(), Error> {
-    let s = format!("{:.2}", entry.time);
-    let t: f64 = s.parse().unwrap();
-    println!(
-        "{}",
-        to_string(&Entry {
-            time: t,
-            event_type: entry.event_type,
-            event_data: entry.event_data,
-        })?
-    );
-    Ok(())
-}
-
-fn clear_terminal(time: &mut f64, step: &f64) -> Result<(), Error> {
-    *time += 18.0 * step;
-    print_entry(Entry {
-        time: *time,
-        event_type: EventType::Output,
-        event_data: "\r\x1b[2J\r\x1b[H".to_string(),
-    })?;
-    *time += 3.0 * step;
-    Ok(())
-}
-
-fn echo_typing(time: &mut f64, step: &f64, line_raw: &str) -> Result<String, Error> {
-    let mut bright_applied = false;
-    for char in line_raw.to_string().chars() {
-        *time += step;
-        if char == '#' {
-            print_entry(Entry {
-                time: *time,
-                event_type: EventType::Output,
-                event_data: "\x1b[1m".to_string(),
-            })?;
-            bright_applied = true;
-        }
-        print_entry(Entry {
-            time: *time,
-            event_type: EventType::Output,
-            event_data: char.to_string(),
-        })?;
-    }
-    // clear
-    if bright_applied {
-        print_entry(Entry {
-            time: *time,
-            event_type: EventType::Output,
-            event_data: "\x1b[0m".to_string(),
-        })?;
-    }
-
-    *time += 3.0 * step;
-    print_entry(Entry {
-        time: *time,
-        event_type: EventType::Output,
-        event_data: "\r\n".to_string(),
-    })?;
-
-    Ok(line_raw.to_string())
-}
-
-fn echo_console_line(
-    time: &mut f64,
-    step: &f64,
-    prompt: &str,
-    line: &str,
-) -> Result<Vec<String>, Error> {
-    *time += step;
-
-    let mut preview_lines: Vec<String> = vec![];
-    preview_lines.push(prompt.to_string());
-
-    let prompt_line: String = if !prompt.is_empty() {
-        format!("\x1b[32m{}\x1b[0m$ ", prompt)
-    } else {
-        "$ ".to_string()
-    };
-
-    print_entry(Entry {
-        time: *time,
-        event_type: EventType::Output,
-        event_data: prompt_line,
-    })?;
-
-    *time += 3.0 * step;
-
-    preview_lines.push(echo_typing(time, step, line)?);
-
-    Ok(preview_lines)
+    hasher.update([0]);
+    if let Some(path) = &cli.merge_entries {
+        hasher.update(read(path)?);
+    }
+    hasher.update([0]);
+    hasher.update(format!(
+        "seed={:?}\ntime_units={:?}\nformat={:?}\npretty={}\nno_final_newline={}\nsplit_on_clear={}\ninitial_offset={:?}\nstart_delay={:?}\nwidth={:?}\nheight={:?}\nstep={:?}\nfrom_commands={}\nfrom_markdown={}\nrebase={}\njitter={:?}\nspeed={:?}\nvariables={:?}\nstrip_ansi={}\ndeterministic={}",
+        cli.seed,
+        cli.time_units,
+        cli.format,
+        cli.pretty,
+        cli.no_final_newline,
+        cli.split_on_clear.is_some(),
+        cli.initial_offset,
+        cli.start_delay,
+        cli.width,
+        cli.height,
+        cli.step,
+        cli.from_commands.is_some(),
+        cli.from_markdown.is_some(),
+        cli.rebase,
+        cli.jitter,
+        cli.speed,
+        cli.variables,
+        cli.strip_ansi,
+        cli.deterministic
+    ));
+    #[cfg(feature = "banner")]
+    hasher.update(format!(
+        "\nbanner_font={}\nbanner_color={:?}",
+        cli.banner_font, cli.banner_color
+    ));
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
 }
 
 #[derive(Debug, StructOpt)]
@@ -218,12 +137,282 @@ fn echo_console_line(
 )]
 struct Cli {
     #[structopt(flatten)]
-    verbose: Verbose,
+    verbose: QuietVerbose,
+
+    /// Scenario file, read from stdin when it is `-` or missing. Several
+    /// files are each converted into a `.cast` file next to them (or into
+    /// `--output-dir`)
+    scenario_files: Vec<String>,
 
-    scenario_file: String,
+    /// Generate the cast from a plain list of commands (one per line)
+    /// instead of a scenario file
+    #[structopt(long, conflicts_with = "scenario-files")]
+    from_commands: Option<String>,
+
+    /// Generate the cast from the `console` and `shell` code blocks of a
+    /// Markdown file instead of a scenario file
+    #[structopt(long, conflicts_with_all = &["scenario-files", "from-commands"])]
+    from_markdown: Option<String>,
 
     #[structopt(name = "preview-file", long, short)]
     svg_preview_file: Option<String>,
+
+    /// Write the SVG preview next to the scenario file, named after it with
+    /// a `.svg` extension (`--preview-file` takes precedence)
+    #[structopt(long)]
+    preview: bool,
+
+    /// Colors of the SVG previews: dark, light or solarized (overrides
+    /// `theme` of the header)
+    #[structopt(long)]
+    theme: Option<String>,
+
+    /// Cut the lines of the SVG previews longer than the terminal with `…`
+    /// instead of wrapping them (sets `preview_truncate` of the header)
+    #[structopt(long)]
+    preview_truncate: bool,
+
+    /// Write an SVG preview playing like the cast (lines show up at their
+    /// time, `--` clears the screen) into this file
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    animated_svg: Option<String>,
+
+    /// Write the cast into this file instead of printing it to stdout
+    #[structopt(long, short, conflicts_with_all = &["split-on-clear", "output-dir"])]
+    output: Option<String>,
+
+    /// Write one cast file per chapter (split on `--` and `#chapter:`) into
+    /// this directory instead of printing a single cast to stdout
+    #[structopt(long)]
+    split_on_clear: Option<String>,
+
+    /// Write the cast into this directory, named after the scenario file (eg.
+    /// `demo.scenario` becomes `demo.cast`), instead of printing it to stdout
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    output_dir: Option<String>,
+
+    /// Merge additional asciicast entries (one JSON array per line) from this
+    /// file into the generated cast
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    merge_entries: Option<String>,
+
+    /// Font used for `#banner:` lines (block, hash or dots)
+    #[cfg(feature = "banner")]
+    #[structopt(long, default_value = "block")]
+    banner_font: String,
+
+    /// Color of `#banner:` lines (black, red, green, yellow, blue, magenta,
+    /// cyan or white)
+    #[cfg(feature = "banner")]
+    #[structopt(long)]
+    banner_color: Option<String>,
+
+    /// Format of the cast: asciicast v2 (a header line followed by one line
+    /// per event) or v1 (a single JSON object, for older players)
+    #[structopt(long, default_value = "v2", possible_values = &["v1", "v2"])]
+    format: CastFormat,
+
+    /// Write a space after every `,` and `:` of the cast to make it easier to
+    /// read and diff, every event stays on its own line
+    #[structopt(long)]
+    pretty: bool,
+
+    /// Check that the generated cast is a valid asciicast v2 recording (eg.
+    /// its times never go back) before writing it
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    validate: bool,
+
+    /// Do not end the very last line of the cast with a newline
+    #[structopt(long)]
+    no_final_newline: bool,
+
+    /// Write every event of the cast as soon as it is rendered instead of
+    /// rendering the whole scenario first, so that memory doesn't grow with
+    /// its length: the header has no `duration` and only asciicast v2 casts
+    /// can be streamed
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "split-on-clear",
+            "preview-file",
+            "preview",
+            "animated-svg",
+            "summary",
+            "captions",
+            "validate",
+            "merge-entries",
+            "no-final-newline",
+            "embed-hash",
+            "hash-only",
+            "validate-header-only",
+            "check"
+        ]
+    )]
+    stream: bool,
+
+    /// Precision of timestamps: seconds rounded to two decimals (s) or exact
+    /// microseconds (us)
+    #[structopt(long, default_value = "s", possible_values = &["s", "us"])]
+    time_units: TimeUnits,
+
+    /// Print the messages of the tool without colors, like when `NO_COLOR`
+    /// is set
+    #[structopt(long)]
+    no_color: bool,
+
+    /// Remove the escape sequences (eg. colors) written in the scenario
+    /// instead of printing them
+    #[structopt(long)]
+    strip_ansi: bool,
+
+    /// Fail when an assertion in the scenario does not hold or on unknown
+    /// directives (eg. a misspelled `#timout:`)
+    #[structopt(long)]
+    strict: bool,
+
+    /// Print a hash of the scenario and the options affecting the output,
+    /// then exit
+    #[structopt(long)]
+    hash_only: bool,
+
+    /// Write the hash of the scenario into the header of the cast
+    #[structopt(long)]
+    embed_hash: bool,
+
+    /// List all directives and their aliases
+    #[structopt(long)]
+    list_directives: bool,
+
+    /// Time before the first event, in steps (overrides `initial_offset` of
+    /// the header)
+    #[structopt(long)]
+    initial_offset: Option<f64>,
+
+    /// Time before the first event in seconds, eg. for casts autoplaying in
+    /// a page, added to `initial_offset` (overrides `start_delay` of the
+    /// header)
+    #[structopt(long)]
+    start_delay: Option<f64>,
+
+    /// Number of columns of the terminal (overrides `width` of the header)
+    #[structopt(long)]
+    width: Option<u32>,
+
+    /// Number of lines of the terminal (overrides `height` of the header)
+    #[structopt(long)]
+    height: Option<u32>,
+
+    /// Time in seconds between two typed characters (overrides `step` of the
+    /// header)
+    #[structopt(long)]
+    step: Option<f64>,
+
+    /// Render the cast into an animated GIF
+    #[cfg(feature = "gif")]
+    #[structopt(long, conflicts_with_all = &["split-on-clear", "stream"])]
+    gif: Option<String>,
+
+    /// Frames per second of the GIF
+    #[cfg(feature = "gif")]
+    #[structopt(long, default_value = "10")]
+    gif_fps: u32,
+
+    /// How many times the GIF plays, 0 means forever
+    #[cfg(feature = "gif")]
+    #[structopt(long, default_value = "0")]
+    gif_loops: u16,
+
+    /// Write the SVG preview rasterized as a PNG image into this file
+    #[cfg(feature = "png")]
+    #[structopt(long, conflicts_with_all = &["split-on-clear", "stream"])]
+    preview_png: Option<String>,
+
+    /// Size of the PNG preview relative to the SVG preview, eg. 2 for
+    /// high-density screens
+    #[cfg(feature = "png")]
+    #[structopt(long, default_value = "1")]
+    preview_png_scale: f32,
+
+    /// Write a JSON summary of the generated cast (duration, number of
+    /// entries and commands, chapters, size, ...) into this file
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    summary: Option<String>,
+
+    /// Write the captions (`#caption:` and `#note:` lines) into this WebVTT
+    /// file
+    #[structopt(long, conflicts_with = "split-on-clear")]
+    captions: Option<String>,
+
+    /// Treat the time of `#entry:` lines as a delay after the previous event
+    /// instead of an absolute timestamp
+    #[structopt(long)]
+    rebase: bool,
+
+    /// Unix timestamp written into the header of the cast instead of the
+    /// current time, eg. for reproducible builds
+    #[structopt(long)]
+    timestamp: Option<i64>,
+
+    /// Generate the exact same cast for the same scenario, eg. for golden
+    /// files: the header has no timestamp, jitter is off and typing profiles
+    /// and shuffle blocks use a fixed seed (unless `--seed` is given)
+    #[structopt(long, conflicts_with = "timestamp")]
+    deterministic: bool,
+
+    /// Only parse the `#!` header line, print the width, height and step it
+    /// sets and exit
+    #[structopt(long)]
+    validate_header_only: bool,
+
+    /// Only parse and validate the whole scenario (header, directives and
+    /// their values), write nothing and exit with a non-zero status on errors
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "output",
+            "output-dir",
+            "preview-file",
+            "preview",
+            "animated-svg",
+            "summary",
+            "captions"
+        ]
+    )]
+    check: bool,
+
+    /// Seed for the random number generator used by typing profiles, jitter
+    /// and shuffle blocks
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Vary every keystroke randomly by up to this fraction of `step` (eg.
+    /// 0.3 for ±30%)
+    #[structopt(long, default_value = "0")]
+    jitter: f64,
+
+    /// Set a variable used as `${NAME}` in commands and output, eg.
+    /// `--define VERSION=1.2` (can be repeated, overrides `#set:` lines)
+    #[structopt(
+        long = "define",
+        value_name = "NAME=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = parse_variable)
+    )]
+    variables: Vec<(String, String)>,
+
+    /// Convert the scenario again whenever it (or a file it includes)
+    /// changes, until interrupted
+    #[cfg(feature = "watch")]
+    #[structopt(
+        long,
+        conflicts_with_all = &["split-on-clear", "hash-only", "validate-header-only", "check"]
+    )]
+    watch: bool,
+
+    /// Playback speed of the cast, eg. 2 halves every delay and 0.5 doubles
+    /// them
+    #[structopt(long, default_value = "1")]
+    speed: f64,
 }
 
 fn main() -> Result<(), Error> {
@@ -232,180 +421,480 @@ fn main() -> Result<(), Error> {
     // Initialize logging
     let log_level = cli.verbose.get_level_filter();
 
+    let color_choice = if colored(&cli) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+
     // stdout/stderr based logger
     TermLogger::init(
         log_level,            // set log level via "-vvv" flags
         Config::default(),    // how to format logs
         TerminalMode::Stderr, // log to stderr
-        ColorChoice::Auto,    // color preference of an end user
+        color_choice,         // color preference of an end user
     )?;
 
-    // check if does not scenario_file exists
-    if !Path::new(&cli.scenario_file).exists() {
-        println!(
-            "\x1b[31mERROR:\x1b[0m scenario file `{}` does not exist!",
-            cli.scenario_file
-        );
-        exit(1);
+    if cli.list_directives {
+        list_directives();
+        return Ok(());
     }
+    // several scenario files are converted one after the other, each into
+    // its own cast file
+    if cli.scenario_files.len() > 1 {
+        let batch_options = [
+            ("--output", cli.output.is_some()),
+            ("--preview-file", cli.svg_preview_file.is_some()),
+            ("--animated-svg", cli.animated_svg.is_some()),
+            ("--split-on-clear", cli.split_on_clear.is_some()),
+            ("--summary", cli.summary.is_some()),
+            ("--captions", cli.captions.is_some()),
+            ("--hash-only", cli.hash_only),
+            ("--validate-header-only", cli.validate_header_only),
+            #[cfg(feature = "gif")]
+            ("--gif", cli.gif.is_some()),
+            #[cfg(feature = "png")]
+            ("--preview-png", cli.preview_png.is_some()),
+            #[cfg(feature = "watch")]
+            ("--watch", cli.watch),
+        ];
+        if let Some((option, _)) = batch_options.iter().find(|(_, set)| *set) {
+            eprintln!(
+                "{} {} can't be used with several scenario files!",
+                error_label(&cli),
+                option
+            );
+            exit(1);
+        }
 
-    // check if svg_preview_file exists
-    if cli.svg_preview_file.is_some() && Path::new(cli.svg_preview_file.as_ref().unwrap()).exists()
-    {
-        println!(
-            "\x1b[31mERROR:\x1b[0m svg preview file `{}` already exist!",
-            cli.svg_preview_file.unwrap()
-        );
+        let mut failed = 0;
+        for scenario_file in &cli.scenario_files {
+            match convert(&cli, scenario_file, true, false) {
+                Ok(_) => eprintln!("{} {}", ok_label(&cli), scenario_file),
+                Err(e) => {
+                    eprintln!("{} {}: {}", error_label(&cli), scenario_file, e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            eprintln!(
+                "{} {} of {} scenario files failed!",
+                error_label(&cli),
+                failed,
+                cli.scenario_files.len()
+            );
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    let scenario_file = cli
+        .scenario_files
+        .first()
+        .cloned()
+        .or_else(|| cli.from_commands.clone())
+        .or_else(|| cli.from_markdown.clone())
+        .unwrap_or_else(|| "-".to_string());
+    #[cfg(feature = "watch")]
+    if cli.watch {
+        if let Err(e) = watch(&cli, &scenario_file) {
+            eprintln!("{} {}", error_label(&cli), e);
+            exit(1);
+        }
+        return Ok(());
+    }
+    if let Err(e) = convert(&cli, &scenario_file, false, false) {
+        eprintln!("{} {}", error_label(&cli), e);
         exit(1);
     }
+    Ok(())
+}
+
+/// How long the scenario must stay unchanged before it is converted again,
+/// editors may write a file several times when saving it.
+#[cfg(feature = "watch")]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Convert the scenario file, then again every time it or one of the files
+/// it includes changes, until interrupted. Errors are printed and the
+/// scenario keeps being watched.
+#[cfg(feature = "watch")]
+fn watch(cli: &Cli, scenario_file: &str) -> Result<(), Error> {
+    if scenario_file == "-" {
+        return Err(format_err!("--watch needs a scenario file!"));
+    }
+    let scenario = Path::new(scenario_file).canonicalize()?;
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mut watched: Vec<PathBuf> = vec![];
+    let mut overwrite = false;
+    loop {
+        let start = Instant::now();
+        let mut files = vec![scenario.clone()];
+        match convert(cli, scenario_file, false, overwrite) {
+            Ok(included) => {
+                info!("converted `{}` in {:.2?}", scenario_file, start.elapsed());
+                files.extend(included);
+            }
+            Err(e) => eprintln!("{} {}", error_label(cli), e),
+        }
+        overwrite = true;
+
+        // the directories are watched since editors often replace a file
+        // instead of writing into it
+        for directory in files.iter().filter_map(|file| file.parent()) {
+            if !watched.iter().any(|watched| watched == directory) {
+                watcher.watch(directory, RecursiveMode::NonRecursive)?;
+                watched.push(directory.to_path_buf());
+            }
+        }
+        loop {
+            let event = receiver.recv()??;
+            if (event.kind.is_create() || event.kind.is_modify())
+                && event.paths.iter().any(|path| files.contains(path))
+            {
+                break;
+            }
+        }
+        while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    }
+}
 
-    // Read lines from scenario_file
-    let first_f = File::open(&cli.scenario_file)?;
-    let mut first_reader = BufReader::new(first_f);
+/// The timestamp of the header of the cast: none with `--deterministic`,
+/// else `--timestamp` or the current time.
+fn timestamp(cli: &Cli) -> Result<Option<i64>, Error> {
+    Ok(match cli.timestamp {
+        _ if cli.deterministic => None,
+        Some(timestamp) => Some(timestamp),
+        None => Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+    })
+}
 
-    // Header
-    let mut first_line = String::new();
-    first_reader.read_line(&mut first_line)?;
+/// Where the cast is written: the `output_file` (creating its directory) or
+/// stdout.
+fn output_writer(output_file: Option<&Path>) -> Result<Box<dyn Write>, Error> {
+    Ok(match output_file {
+        Some(output_file) => {
+            if let Some(dir) = output_file.parent() {
+                create_dir_all(dir)?;
+            }
+            Box::new(BufWriter::new(File::create(output_file)?))
+        }
+        None => Box::new(BufWriter::new(stdout().lock())),
+    })
+}
 
-    let header: ScenarioHeader = if let Some(stripped) = first_line.strip_prefix("#! ") {
-        from_str(stripped)?
-    } else {
-        from_str("{}")?
-    };
-    let asciicast_header = Header {
-        version: 2,
-        width: header.width,
-        height: header.height,
-        timestamp: None,
-        duration: None,
-        idle_time_limit: None,
-        command: None,
-        title: None,
-        env: None,
+/// Convert one scenario file (`-` for stdin) as set by the options, with
+/// `batch` the cast is written next to it unless `--output-dir` is given and
+/// with `overwrite` existing files are replaced. Gives the files included by
+/// the scenario.
+fn convert(
+    cli: &Cli,
+    scenario_file: &str,
+    batch: bool,
+    overwrite: bool,
+) -> Result<Vec<PathBuf>, Error> {
+    let from_stdin = scenario_file == "-";
+
+    // check if does not scenario_file exists
+    if !from_stdin && !Path::new(scenario_file).exists() {
+        return Err(format_err!(
+            "scenario file `{}` does not exist!",
+            scenario_file
+        ));
+    }
+
+    // check if the svg preview file (given or derived from the scenario
+    // name) exists
+    let svg_preview_file = match &cli.svg_preview_file {
+        Some(svg_preview_file) => Some(PathBuf::from(svg_preview_file)),
+        None if cli.preview && from_stdin => {
+            return Err(format_err!(
+                "--preview needs a scenario file to name the preview after, use --preview-file!"
+            ));
+        }
+        None if cli.preview => Some(Path::new(scenario_file).with_extension("svg")),
+        None => None,
     };
-    println!("{}", to_string(&asciicast_header)?);
-
-    // The rest of the file
-    // Read lines from scenario_file
-    let mut preview_lines: Vec<Vec<String>> = vec![];
-    let f = File::open(cli.scenario_file)?;
-    let reader = BufReader::new(f);
-    let mut time = 3.0 * header.step;
-    for (index, maybe_line) in reader.lines().enumerate() {
-        let line = maybe_line?;
-        // skip when first line starts with "#! " since we already processed it above
-        if index == 0 && line.starts_with("#! ") {
-            continue;
-
-        // lines starting with "#timeout: " will create defined timeout
-        } else if let Some(stripped) = line.strip_prefix("#timeout:") {
-            {
-                let timeout: f64 = stripped.trim().parse()?;
-                time += timeout;
+    if let Some(svg_preview_file) = &svg_preview_file {
+        if !overwrite && svg_preview_file.exists() {
+            return Err(format_err!(
+                "svg preview file `{}` already exist!",
+                svg_preview_file.display()
+            ));
+        }
+    }
+
+    // check if the cast file (given or derived from the scenario name) exists
+    if from_stdin && (cli.output_dir.is_some() || batch) {
+        return Err(format_err!(
+            "--output-dir needs a scenario file to name the cast after!"
+        ));
+    }
+    let output_file = cli
+        .output
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| match &cli.output_dir {
+            Some(dir) => {
+                let stem = Path::new(scenario_file)
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_os_string();
+                Some(Path::new(dir).join(stem).with_extension("cast"))
             }
+            None if batch => Some(Path::new(scenario_file).with_extension("cast")),
+            None => None,
+        });
+    if let Some(output_file) = &output_file {
+        if !overwrite && output_file.exists() {
+            return Err(format_err!(
+                "output file `{}` already exist!",
+                output_file.display()
+            ));
+        }
+    }
 
-        // skip lines starting with "#"
-        } else if line.starts_with('#') {
-            continue;
+    // check if summary file exists
+    if let Some(summary) = &cli.summary {
+        if !overwrite && Path::new(summary).exists() {
+            return Err(format_err!("summary file `{}` already exist!", summary));
+        }
+    }
 
-        // lines starting with "$ " display as console lines
-        } else if let Some(stripped) = line.strip_prefix("$ ") {
-            preview_lines.push(echo_console_line(&mut time, &header.step, "", stripped)?);
+    // check if captions file exists
+    if let Some(captions) = &cli.captions {
+        if !overwrite && Path::new(captions).exists() {
+            return Err(format_err!("captions file `{}` already exist!", captions));
+        }
+    }
 
-        // lines starting with "(nix-shell) $ " display as console lines
-        } else if let Some(stripped) = line.strip_prefix("(nix-shell) $ ") {
-            preview_lines.push(echo_console_line(
-                &mut time,
-                &header.step,
-                "(nix-shell) ",
-                stripped,
-            )?);
+    // check if the animated svg preview exists
+    if let Some(animated_svg) = &cli.animated_svg {
+        if !overwrite && Path::new(animated_svg).exists() {
+            return Err(format_err!(
+                "animated svg preview file `{}` already exist!",
+                animated_svg
+            ));
+        }
+    }
 
-        // lines starting with "--" will clear display
-        } else if line.starts_with("--") {
-            clear_terminal(&mut time, &header.step)?;
+    // check if gif exists
+    #[cfg(feature = "gif")]
+    if let Some(gif) = &cli.gif {
+        if !overwrite && Path::new(gif).exists() {
+            return Err(format_err!("gif file `{}` already exist!", gif));
+        }
+    }
 
-        // timeout
-        } else if line.trim() == "" {
-            time += 3.0 * header.step;
+    // check if the png preview exists
+    #[cfg(feature = "png")]
+    if let Some(preview_png) = &cli.preview_png {
+        if !overwrite && Path::new(preview_png).exists() {
+            return Err(format_err!(
+                "png preview file `{}` already exist!",
+                preview_png
+            ));
+        }
+    }
 
-        // everything else print immediately
-        } else {
-            print_entry(Entry {
-                time,
-                event_type: EventType::Output,
-                event_data: format!("{}\r\n", line.clone()),
-            })?;
-            preview_lines.push(vec![line.to_string()]);
+    // a list of commands or a Markdown file has no header
+    let format = if cli.from_commands.is_some() {
+        InputFormat::Commands
+    } else if cli.from_markdown.is_some() {
+        InputFormat::Markdown
+    } else {
+        InputFormat::Scenario
+    };
+    let render_options = RenderOptions {
+        format,
+        initial_offset: cli.initial_offset,
+        start_delay: cli.start_delay,
+        width: cli.width,
+        height: cli.height,
+        step: cli.step,
+        theme: cli.theme.clone(),
+        preview_truncate: cli.preview_truncate,
+        seed: match cli.seed {
+            None if cli.deterministic => Some(DETERMINISTIC_SEED),
+            seed => seed,
+        },
+        variables: cli.variables.clone(),
+        jitter: if cli.deterministic { 0.0 } else { cli.jitter },
+        file: Some(PathBuf::from(scenario_file)).filter(|_| !from_stdin),
+        rebase: cli.rebase,
+        strict: cli.strict,
+        split: cli.split_on_clear.is_some(),
+        check: cli.check,
+        speed: cli.speed,
+        strip_ansi: cli.strip_ansi,
+        preview: !cli.stream,
+        #[cfg(feature = "banner")]
+        banner_font: cli.banner_font.clone(),
+        #[cfg(feature = "banner")]
+        banner_color: cli.banner_color.clone(),
+    };
+
+    // the cast is written while the scenario is read, line after line
+    if cli.stream {
+        if cli.format == CastFormat::V1 {
+            return Err(format_err!("--stream only writes asciicast v2 casts!"));
         }
+        let options = OutputOptions {
+            format: cli.format,
+            time_units: cli.time_units,
+            pretty: cli.pretty,
+            scenario_hash: None,
+            timestamp: timestamp(cli)?,
+        };
+        let mut out = output_writer(output_file.as_deref())?;
+        let scenario = if from_stdin {
+            stream(stdin().lock(), &render_options, &mut out, &options)?
+        } else {
+            let input = BufReader::new(File::open(scenario_file)?);
+            stream(input, &render_options, &mut out, &options)?
+        };
+        out.flush()?;
+        return Ok(scenario.included);
+    }
+
+    // Header
+    // the input is read once since stdin can't be reopened
+    let input = if from_stdin {
+        let mut input = vec![];
+        stdin().read_to_end(&mut input)?;
+        input
+    } else {
+        read(scenario_file)?
+    };
+
+    if cli.validate_header_only {
+        let mut header = match format {
+            InputFormat::Scenario => read_header(&input[..])?,
+            _ => parse_header("")?,
+        };
+        render_options.override_header(&mut header);
+        println!(
+            "width: {}, height: {}, step: {}",
+            header.width, header.height, header.step
+        );
+        return Ok(vec![]);
+    }
+
+    if cli.hash_only {
+        let included = included_files(&input[..], &render_options)?;
+        println!("{}", scenario_hash(cli, &input, &included)?);
+        return Ok(vec![]);
+    }
+    let mut scenario = render(&input[..], &render_options)?;
+    if cli.check {
+        return Ok(vec![]);
     }
 
-    match cli.svg_preview_file {
-        Some(filename) => {
-            let mask_rect = Rectangle::new()
-                .set("x", "0")
-                .set("y", "0")
-                .set("width", "824")
-                .set("height", "623")
-                .set("fill", "#fff");
-            let mask = Mask::new().set("id", "bigterminal-mask").add(mask_rect);
-            let rect = Rectangle::new()
-                .set("class", "background")
-                .set("y", "0")
-                .set("x", "0")
-                .set("width", "824")
-                .set("height", "623");
-
-            let mut text = TextElement::new()
-                .set("mask", "url(#bigterminal-mask)")
-                .set("transform", "translate(0 0)")
-                .set("y", "0")
-                .set("x", "0")
-                .set("xml:space", "preserve");
-
-            for preview_line in preview_lines.into_iter() {
-                let mut tspan = TSpan::new().set("x", "0").set("dy", "1.2em");
-
-                for item in preview_line {
-                    if item.is_empty() {
-                        tspan = tspan.append(Text::new("$ ".to_string()));
-                    } else {
-                        let parts: Vec<&str> = item.splitn(2, '#').collect();
-                        if parts.len() == 1 {
-                            tspan = tspan.append(Text::new(encode_safe(parts[0])));
-                        } else {
-                            tspan = tspan.append(Text::new(encode_safe(parts[0])));
-                            tspan =
-                                tspan.append(TSpan::new().set("class", "fg-15").append(Text::new(
-                                    encode_safe(parts.clone().split_off(1).join("").as_str()),
-                                )));
-                        }
-                        tspan = tspan.append(
-                            TSpan::new()
-                                .set("class", "fg-2")
-                                .append(Text::new(encode_safe(item.as_str()))),
-                        );
-                    }
+    let options = OutputOptions {
+        format: cli.format,
+        time_units: cli.time_units,
+        pretty: cli.pretty,
+        scenario_hash: if cli.embed_hash {
+            Some(scenario_hash(cli, &input, &scenario.included)?)
+        } else {
+            None
+        },
+        timestamp: timestamp(cli)?,
+    };
+
+    if let Some(path) = &cli.merge_entries {
+        merge_entries(&mut scenario.entries, path)?;
+    }
+    if cli.no_final_newline {
+        strip_final_newline(&mut scenario.entries);
+    }
+    // the captions, summary and previews follow the times of the cast
+    scenario.round_times(&options);
+
+    if let Some(path) = &cli.captions {
+        write_captions(path, &scenario.captions, scenario.end)?;
+    }
+    #[cfg(feature = "gif")]
+    if let Some(path) = &cli.gif {
+        gif::render(
+            path,
+            &scenario.entries,
+            &scenario.header,
+            &gif::GifOptions {
+                fps: cli.gif_fps,
+                loops: cli.gif_loops,
+            },
+        )?;
+    }
+
+    if let Some(path) = &cli.summary {
+        let summary = Summary::new(
+            &scenario,
+            &options,
+            scenario_hash(cli, &input, &scenario.included)?,
+        )?;
+        writeln!(File::create(path)?, "{}", to_string(&summary)?)?;
+    }
+
+    // when splitting on clear every clip gets its own header
+    match (&cli.split_on_clear, &output_file) {
+        (Some(dir), _) => {
+            let mut clips = Clips::new(dir, options)?;
+            for clip in &scenario.clips {
+                clips.write(&scenario.header, clip)?;
+            }
+        }
+        (None, output_file) => {
+            // the cast is only held in memory to be validated before anything
+            // is written, otherwise every entry is written as it is printed
+            let cast = if cli.validate {
+                if cli.format == CastFormat::V1 {
+                    return Err(format_err!("--validate only checks asciicast v2 casts!"));
                 }
-                text = text.add(tspan);
+                let mut cast = vec![];
+                print_cast(&mut cast, &scenario.header, &scenario.entries, &options)?;
+                validate_cast(from_utf8(&cast)?)
+                    .map_err(|e| format_err!("the generated cast is invalid, {}", e))?;
+                Some(cast)
+            } else {
+                None
+            };
+            let mut out = output_writer(output_file.as_deref())?;
+            match cast {
+                Some(cast) => out.write_all(&cast)?,
+                None => print_cast(&mut out, &scenario.header, &scenario.entries, &options)?,
             }
-
-            let svg_preview = Document::new()
-                .set("xmlns:dc", "http://purl.org/dc/elements/1.1/")
-                .set("xmlns:cc", "http://creativecommons.org/ns#")
-                .set("xmlns:rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#")
-                .set("xmlns:svg", "http://www.w3.org/2000/svg")
-                .set("xmlns", "http://www.w3.org/2000/svg")
-                .set("version", "1.1")
-                .set("width", "100%")
-                .set("viewBox", "0 0 824 623")
-                .set("preserveAspectRatio", "xMidYMid meet")
-                .add(mask)
-                .add(rect)
-                .add(text);
-            svg::save(filename, &svg_preview)?;
-            Ok(())
+            out.flush()?;
         }
-        None => Ok(()),
     }
+
+    if let Some(filename) = &cli.animated_svg {
+        svg::save(
+            filename,
+            &animated_svg_preview(
+                &scenario.header,
+                scenario.preview_lines.clone(),
+                &scenario.preview_times,
+                &scenario.hints,
+                scenario.end,
+            ),
+        )?;
+    }
+    #[cfg(feature = "png")]
+    if let Some(filename) = &cli.preview_png {
+        png::render(
+            filename,
+            &svg_preview(&scenario.header, scenario.preview_lines.clone()),
+            cli.preview_png_scale,
+        )?;
+    }
+    if let Some(filename) = &svg_preview_file {
+        svg::save(
+            filename,
+            &svg_preview(&scenario.header, scenario.preview_lines),
+        )?;
+    }
+    Ok(scenario.included)
 }
@@ -0,0 +1,32 @@
+//! Rasterize the SVG preview into a PNG image (`--preview-png`).
+
+use failure::{format_err, Error};
+use resvg::{tiny_skia, usvg};
+use svg::Document;
+
+/// Render `document` into a PNG image at `path`, `scale` times its size.
+pub fn render(path: &str, document: &Document, scale: f32) -> Result<(), Error> {
+    if scale <= 0.0 {
+        return Err(format_err!("--preview-png-scale must be positive"));
+    }
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(&document.to_string(), &options)?;
+    let size = tree.size().to_int_size().scale_by(scale).ok_or_else(|| {
+        format_err!(
+            "can not scale a {}x{} image by {}",
+            tree.size().width(),
+            tree.size().height(),
+            scale
+        )
+    })?;
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| format_err!("can not create a {}x{} image", size.width(), size.height()))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.save_png(path)?;
+    Ok(())
+}
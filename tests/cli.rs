@@ -0,0 +1,230 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Run the command line tool with `args`, `stdin` as its input.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_asciinema-scenario"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// The times of the events of a cast printed by the tool.
+fn event_times(output: &Output) -> Vec<f64> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).unwrap()[0]
+                .as_f64()
+                .unwrap()
+        })
+        .collect()
+}
+
+/// An empty directory for the files written by a test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "asciinema-scenario-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn split_on_clear_names_clips_after_chapters() {
+    let dir = temp_dir("split");
+    let output = run(
+        &["--split-on-clear", dir.to_str().unwrap()],
+        "#chapter: Getting started\n$ ls\n--\n$ pwd\n",
+    );
+    assert!(output.status.success());
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["chapter_01_getting-started.cast", "chapter_02.cast"]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn time_units_set_the_precision_of_times() {
+    let args = ["--timestamp", "0", "--step", "0.0123", "--time-units"];
+    let in_seconds = event_times(&run(&[&args[..], &["s"]].concat(), "$ ls\n"));
+    let in_microseconds = event_times(&run(&[&args[..], &["us"]].concat(), "$ ls\n"));
+    assert_eq!(in_seconds, [0.05, 0.1, 0.11, 0.15]);
+    assert_eq!(in_microseconds, [0.0492, 0.0984, 0.1107, 0.1476]);
+}
+
+#[test]
+fn captions_start_at_their_directive() {
+    let dir = temp_dir("captions");
+    std::fs::create_dir_all(&dir).unwrap();
+    let captions = dir.join("demo.vtt");
+    let output = run(
+        &["--captions", captions.to_str().unwrap()],
+        "#! {\"initial_offset\": 0}\n#caption: One\n#timeout: 2\n#caption: Two\n#timeout: 1.5\n#caption:\nend\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(&captions).unwrap(),
+        "WEBVTT\n\n1\n00:00:00.000 --> 00:00:02.000\nOne\n\n2\n00:00:02.000 --> 00:00:03.500\nTwo\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn warnings_are_shown_unless_quiet() {
+    let output = run(&[], "#timout: 2\n$ ls\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown directive `#timout:`"));
+    let output = run(&["-q"], "#timout: 2\n$ ls\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn resize_is_written_as_a_resize_event() {
+    let output = run(
+        &["--timestamp", "0"],
+        "#! {\"initial_offset\": 0}\n#resize: 120x40\n",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).lines().nth(1),
+        Some("[0.0,\"r\",\"120x40\"]")
+    );
+}
+
+#[test]
+fn hash_covers_included_files() {
+    let dir = temp_dir("hash");
+    std::fs::create_dir_all(&dir).unwrap();
+    let scenario = dir.join("demo.scenario");
+    std::fs::write(&scenario, "#include: setup.scenario\n$ ls\n").unwrap();
+    let hash = |setup: &str| {
+        std::fs::write(dir.join("setup.scenario"), setup).unwrap();
+        run(&[scenario.to_str().unwrap(), "--hash-only"], "").stdout
+    };
+    assert_ne!(hash("$ cd /tmp\n"), hash("$ cd /var\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn header_is_the_same_on_every_run() {
+    let scenario = "#! {\"env\": {\"TERM\": \"xterm\", \"SHELL\": \"/bin/zsh\", \"LANG\": \"C\", \"EDITOR\": \"vi\"}, \"log_colors\": {\"WARN\": \"yellow\", \"INFO\": \"cyan\"}}\n$ ls\n";
+    let header = || {
+        let output = run(&["--timestamp", "0"], scenario);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap()
+            .to_string()
+    };
+    let first = header();
+    assert!(first
+        .contains("{\"EDITOR\":\"vi\",\"LANG\":\"C\",\"SHELL\":\"/bin/zsh\",\"TERM\":\"xterm\"}"));
+    for _ in 0..3 {
+        assert_eq!(header(), first);
+    }
+}
+
+#[test]
+fn empty_scenario_gives_a_header_only_cast() {
+    let output = run(&["--timestamp", "0"], "");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"version\":2,\"width\":77,\"height\":20,\"timestamp\":0}\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("scenario produced no events"));
+    let output = run(&["--strict"], "");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("scenario produced no events"));
+}
+
+#[test]
+fn huge_waits_are_an_error() {
+    let output = run(&[], "$ a{{pause:1e300}}b\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("max_timeout"));
+
+    let dir = temp_dir("huge-embed");
+    std::fs::create_dir_all(&dir).unwrap();
+    let cast = dir.join("huge.cast");
+    std::fs::write(
+        &cast,
+        "{\"version\": 2, \"width\": 80, \"height\": 24}\n[1e300, \"o\", \"x\"]\n",
+    )
+    .unwrap();
+    let output = run(&[], &format!("#embed: {}\n", cast.display()));
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("max_timeout"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn from_commands_is_the_same_as_explicit_commands() {
+    let dir = temp_dir("from-commands");
+    std::fs::create_dir_all(&dir).unwrap();
+    let commands = dir.join("commands.txt");
+    std::fs::write(&commands, "ls -la\n# a comment\n\ncd /tmp && pwd\n").unwrap();
+    let args = ["--deterministic", "--width", "100"];
+    let from_commands = run(
+        &[&args[..], &["--from-commands", commands.to_str().unwrap()]].concat(),
+        "",
+    );
+    let explicit = run(&args, "$ ls -la\n# a comment\n\n$ cd /tmp && pwd\n");
+    assert!(from_commands.status.success());
+    assert!(!from_commands.stdout.is_empty());
+    assert_eq!(from_commands.stdout, explicit.stdout);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn undefined_variables_are_warned_about() {
+    let output = run(&[], "$ ls\necho ${NAME}\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("undefined variable `NAME` on line 2"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("echo ${NAME}"));
+
+    let output = run(&["--strict"], "$ ls\necho ${NAME}\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2: undefined variable `NAME`"));
+
+    let output = run(&["--define", "NAME=cli"], "#set: NAME=set\necho ${NAME}\n");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("echo cli"));
+}
+
+#[test]
+fn stream_writes_the_cast_of_stdin() {
+    let scenario = "#marker: Start\n$ ls\na b\n";
+    let streamed = run(&["--deterministic", "--stream"], scenario);
+    assert!(streamed.status.success());
+    let cast = run(&["--deterministic"], scenario);
+    assert_eq!(event_times(&streamed), event_times(&cast));
+    let header = String::from_utf8_lossy(&streamed.stdout);
+    assert!(!header.lines().next().unwrap().contains("duration"));
+
+    let output = run(&["--stream", "--validate"], scenario);
+    assert!(!output.status.success());
+    let output = run(&["--stream", "--format", "v1"], scenario);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--stream only writes asciicast v2 casts!"));
+}
@@ -0,0 +1,36 @@
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use std::process::Command;
+
+/// Every `tests/golden/*.scenario` converted with `--deterministic` gives
+/// the cast next to it. After an intended change of the output, regenerate
+/// them with eg.
+/// `asciinema-scenario tests/golden/demo.scenario --deterministic > tests/golden/demo.cast`.
+#[test]
+fn golden_casts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut scenarios = 0;
+    for entry in read_dir(&dir).unwrap() {
+        let scenario = entry.unwrap().path();
+        if scenario
+            .extension()
+            .is_none_or(|extension| extension != "scenario")
+        {
+            continue;
+        }
+        let output = Command::new(env!("CARGO_BIN_EXE_asciinema-scenario"))
+            .arg(&scenario)
+            .arg("--deterministic")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", scenario.display());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            read_to_string(scenario.with_extension("cast")).unwrap(),
+            "{} doesn't give the golden cast",
+            scenario.display()
+        );
+        scenarios += 1;
+    }
+    assert!(scenarios > 0);
+}
@@ -0,0 +1,714 @@
+use asciinema_scenario::{
+    animated_svg_preview, parse_header, print_cast, render, stream, strip_final_newline,
+    svg_preview, validate_cast, CastFormat, Entry, EventType, InputFormat, OutputOptions,
+    RenderOptions, Scenario, ScenarioBuilder, Summary, TimeUnits,
+};
+
+fn render_str(scenario: &str, options: &RenderOptions) -> Scenario {
+    render(scenario.as_bytes(), options).unwrap()
+}
+
+/// The time and data of the `event_type` events of `entries`.
+fn events(entries: &[Entry], event_type: EventType) -> Vec<(f64, &str)> {
+    entries
+        .iter()
+        .filter(|entry| entry.event_type == event_type)
+        .map(|entry| (entry.time, entry.event_data.as_str()))
+        .collect()
+}
+
+#[test]
+fn chapters_start_when_the_screen_is_cleared() {
+    let scenario = render_str(
+        "#chapter: Intro\n$ ls\n#chapter: Getting started\n$ pwd\n",
+        &RenderOptions::default(),
+    );
+    let labels: Vec<&str> = scenario
+        .chapters
+        .iter()
+        .map(|(label, _)| label.as_str())
+        .collect();
+    assert_eq!(labels, ["Intro", "Getting started"]);
+    assert!(scenario.chapters[0].1 < scenario.chapters[1].1);
+}
+
+#[test]
+fn split_on_clear_restarts_the_time_of_every_clip() {
+    let options = RenderOptions {
+        split: true,
+        ..RenderOptions::default()
+    };
+    let scenario = render_str("#chapter: Intro\n$ ls\n--\n$ pwd\n", &options);
+    assert!(scenario.entries.is_empty());
+    assert_eq!(scenario.clips.len(), 2);
+    assert_eq!(scenario.clips[0].label.as_deref(), Some("Intro"));
+    assert_eq!(scenario.clips[1].label, None);
+    assert_eq!(
+        scenario.clips[0].entries[0].time,
+        scenario.clips[1].entries[0].time
+    );
+}
+
+#[test]
+fn final_newline_is_stripped_from_the_last_output() {
+    for scenario in &["$ ls\na b\n", "#! {\"manage_cursor\": true}\n$ ls\na b\n"] {
+        let mut entries = render_str(scenario, &RenderOptions::default()).entries;
+        strip_final_newline(&mut entries);
+        let last = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.event_type == EventType::Output && entry.event_data.contains('b'))
+            .unwrap();
+        assert_eq!(last.event_data, "a b");
+    }
+}
+
+#[test]
+fn failed_assertions_are_errors_when_checking() {
+    let options = RenderOptions {
+        check: true,
+        ..RenderOptions::default()
+    };
+    let error = render("#assert-lines: <= 1\n$ ls\na b\n".as_bytes(), &options).unwrap_err();
+    assert!(error.to_string().contains("#assert-lines:"));
+    render_str("#assert-lines: <= 2\n$ ls\na b\n", &options);
+}
+
+#[test]
+fn first_entry_follows_the_initial_offset() {
+    for (initial_offset, time) in &[(0.0, 0.0), (5.0, 0.5)] {
+        let scenario = format!(
+            "#! {{\"step\": 0.1, \"initial_offset\": {}}}\nhello\n",
+            initial_offset
+        );
+        let entries = render_str(&scenario, &RenderOptions::default()).entries;
+        assert_eq!(entries[0].time, *time);
+    }
+}
+
+#[test]
+fn hints_only_show_in_the_animated_svg_preview() {
+    let without = render_str("$ ls\na b\n", &RenderOptions::default());
+    let with = render_str(
+        "$ ls\n#hint: Press q to quit\na b\n",
+        &RenderOptions::default(),
+    );
+    assert_eq!(with.entries, without.entries);
+    assert_eq!(with.hints, [(without.end, "Press q to quit".to_string())]);
+    let svg = animated_svg_preview(
+        &with.header,
+        with.preview_lines,
+        &with.preview_times,
+        &with.hints,
+        with.end,
+    )
+    .to_string();
+    assert!(svg.contains("class=\"hint\""));
+    assert!(svg.contains("Press q to quit"));
+}
+
+#[test]
+fn svg_preview_shows_every_command_once() {
+    let scenario = render_str(
+        "$ echo hello\nhello\n$ git status # what changed\n",
+        &RenderOptions::default(),
+    );
+    let svg = svg_preview(&scenario.header, scenario.preview_lines).to_string();
+    assert_eq!(svg.matches("echo hello").count(), 1);
+    assert_eq!(svg.matches("git status").count(), 1);
+    assert_eq!(svg.matches("# what changed").count(), 1);
+}
+
+#[test]
+fn wide_characters_take_a_step_per_cell() {
+    let scenario = render_str(
+        "#! {\"initial_offset\": 0, \"prompt_delay\": 0}\n$ a中b\n",
+        &RenderOptions::default(),
+    );
+    assert_eq!(
+        events(&scenario.entries, EventType::Output)[1..4],
+        [(0.2, "a"), (0.4, "中"), (0.5, "b")]
+    );
+}
+
+#[test]
+fn zwj_emoji_is_typed_at_once() {
+    let scenario = render_str("$ 👨\u{200d}👩\u{200d}👧\n", &RenderOptions::default());
+    let outputs = events(&scenario.entries, EventType::Output);
+    assert_eq!(
+        outputs
+            .iter()
+            .filter(|(_, data)| data.contains('👩'))
+            .count(),
+        1
+    );
+    assert!(outputs
+        .iter()
+        .any(|(_, data)| *data == "👨\u{200d}👩\u{200d}👧"));
+}
+
+#[test]
+fn markers_are_marker_events() {
+    let scenario = render_str(
+        "#! {\"initial_offset\": 0}\n#marker: Introduction\n#timeout: 1.5\n#marker: Usage\n",
+        &RenderOptions::default(),
+    );
+    assert_eq!(
+        events(&scenario.entries, EventType::Marker),
+        [(0.0, "Introduction"), (1.5, "Usage")]
+    );
+}
+
+#[test]
+fn tabs_expand_to_the_next_tab_stop() {
+    let options = RenderOptions {
+        strict: true,
+        ..RenderOptions::default()
+    };
+    let scenario = render_str("#assert-width: 10\n\tab\n", &options);
+    assert_eq!(scenario.entries[0].event_data, "\tab\r\n");
+    render_str("#! {\"tabstop\": 4}\n#assert-width: 6\n\tab\n", &options);
+    assert!(render("#assert-width: 3\n\tab\n".as_bytes(), &options).is_err());
+}
+
+#[test]
+fn front_matter_needs_yaml_fields() {
+    let scenario = render_str("---\nwidth: 50\n---\n$ ls\n", &RenderOptions::default());
+    assert_eq!(scenario.header.width, 50);
+    assert_eq!(scenario.commands, 1);
+    // without fields the `---` lines clear the display
+    let scenario = render_str("---\n$ ls\n---\n$ pwd\n", &RenderOptions::default());
+    assert_eq!(scenario.header.width, 77);
+    assert_eq!(scenario.commands, 2);
+}
+
+#[test]
+fn validate_cast_rejects_broken_casts() {
+    let header = "{\"version\":2,\"width\":80,\"height\":24}";
+    validate_cast(&format!(
+        "{}\n[0.5,\"o\",\"a\"]\n[1.0,\"m\",\"b\"]\n",
+        header
+    ))
+    .unwrap();
+    for (cast, error) in &[
+        ("".to_string(), "empty"),
+        (
+            "{\"version\":1,\"width\":80,\"height\":24}".to_string(),
+            "version",
+        ),
+        ("{\"version\":2}".to_string(), "width"),
+        (format!("{}\n[0.5,\"x\",\"a\"]", header), "line 2"),
+        (
+            format!("{}\n[1.0,\"o\",\"a\"]\n[0.5,\"o\",\"b\"]", header),
+            "before",
+        ),
+    ] {
+        let message = validate_cast(cast).unwrap_err().to_string();
+        assert!(message.contains(error), "{}", message);
+    }
+}
+
+#[test]
+fn rounded_times_stay_increasing_and_consistent() {
+    let mut scenario = render_str(
+        "#! {\"step\": 0.001}\n#chapter: One\n$ ls -la\n#chapter: Two\n$ pwd\n",
+        &RenderOptions::default(),
+    );
+    let options = OutputOptions {
+        format: CastFormat::V2,
+        time_units: TimeUnits::Seconds,
+        scenario_hash: None,
+        timestamp: None,
+        pretty: false,
+    };
+    scenario.round_times(&options);
+    let times: Vec<f64> = scenario.entries.iter().map(|entry| entry.time).collect();
+    assert!(times.windows(2).all(|times| times[0] < times[1]));
+    let summary = Summary::new(&scenario, &options, String::new()).unwrap();
+    assert_eq!(summary.duration, *times.last().unwrap());
+    // the second chapter starts after the first command is typed
+    let ls = scenario
+        .entries
+        .iter()
+        .find(|entry| entry.event_data == "a")
+        .unwrap();
+    assert!(summary.chapters[1].time > ls.time);
+}
+
+#[test]
+fn summary_has_the_markers_and_final_size() {
+    let scenario = render_str(
+        "#marker: One\n$ ls\n#entry: [100, \"o\", \"x\"]\n#resize: 120x40\n#marker: Two\n",
+        &RenderOptions::default(),
+    );
+    let options = OutputOptions {
+        format: CastFormat::V2,
+        time_units: TimeUnits::Seconds,
+        scenario_hash: None,
+        timestamp: None,
+        pretty: false,
+    };
+    let summary = Summary::new(&scenario, &options, String::new()).unwrap();
+    let markers: Vec<&str> = summary
+        .markers
+        .iter()
+        .map(|marker| marker.label.as_str())
+        .collect();
+    assert_eq!(markers, ["One", "Two"]);
+    assert!(summary.markers[0].time < summary.markers[1].time);
+    assert_eq!((summary.width, summary.height), (120, 40));
+    assert_eq!(summary.duration, 100.0);
+}
+
+#[test]
+fn svg_preview_shows_the_last_screen() {
+    let scenario = render_str(
+        "$ echo before\nbefore\n--\nafter\n",
+        &RenderOptions::default(),
+    );
+    let svg = svg_preview(&scenario.header, scenario.preview_lines).to_string();
+    assert!(!svg.contains("before"));
+    assert_eq!(svg.matches("after").count(), 1);
+    assert_eq!(svg.matches("<tspan").count(), 1);
+}
+
+#[test]
+fn shebang_is_not_a_header() {
+    let scenario = render_str(
+        "#!/usr/bin/env asciinema-scenario\n$ ls\n",
+        &RenderOptions::default(),
+    );
+    assert_eq!((scenario.header.width, scenario.header.height), (77, 20));
+    assert_eq!(scenario.commands, 1);
+}
+
+#[test]
+fn pasted_commands_follow_the_prompt_delays() {
+    let scenario = render_str(
+        "#! {\"rprompt\": \"main\", \"prompt_delay\": 1, \"command_delay\": 2, \"initial_offset\": 0}\n$! ls\n",
+        &RenderOptions::default(),
+    );
+    let outputs = events(&scenario.entries, EventType::Output);
+    assert_eq!(
+        outputs[0],
+        (0.1, "$ \u{1b}7\u{1b}[999C\u{1b}[3Dmain\u{1b}8")
+    );
+    assert_eq!(outputs[1..], [(0.2, "ls"), (0.4, "\r\n")]);
+}
+
+#[test]
+fn sgr_sequences_are_kept_but_take_no_room() {
+    let line = "\u{1b}[31mred\u{1b}[0m plain";
+    let options = RenderOptions {
+        strict: true,
+        ..RenderOptions::default()
+    };
+    let scenario = render_str(&format!("#assert-width: 9\n{}\n", line), &options);
+    assert_eq!(scenario.entries[0].event_data, format!("{}\r\n", line));
+    let svg = svg_preview(&scenario.header, scenario.preview_lines).to_string();
+    assert!(svg.contains("class=\"fg-1\""));
+    assert!(!svg.contains("[31m"));
+
+    let options = RenderOptions {
+        strip_ansi: true,
+        ..RenderOptions::default()
+    };
+    let scenario = render_str(&format!("{}\n", line), &options);
+    assert_eq!(scenario.entries[0].event_data, "red plain\r\n");
+}
+
+#[test]
+fn slow_typing_has_larger_gaps() {
+    let scenario = render_str(
+        "#typing: slow\n$ ab\n#typing: normal\n$ ab\n#typing: fast\n$ ab\n",
+        &RenderOptions::default(),
+    );
+    let outputs = events(&scenario.entries, EventType::Output);
+    let gaps: Vec<f64> = outputs
+        .windows(2)
+        .filter(|pair| pair[0].1 == "a" && pair[1].1 == "b")
+        .map(|pair| ((pair[1].0 - pair[0].0) * 1000.0).round())
+        .collect();
+    assert_eq!(gaps, [200.0, 100.0, 50.0]);
+}
+
+#[test]
+fn builder_renders_like_a_scenario() {
+    let header = r#"#! {"manage_cursor": true, "idle_time_limit": 1.0}"#;
+    let entries = ScenarioBuilder::new()
+        .header(parse_header(header).unwrap())
+        .line_command("ls")
+        .line_output("a b")
+        .timeout(5.0)
+        .clear()
+        .build()
+        .unwrap();
+    let scenario = render_str(
+        &format!("{}\n$ ls\na b\n#timeout: 5\n--\n", header),
+        &RenderOptions::default(),
+    );
+    assert_eq!(entries, scenario.entries);
+}
+
+#[test]
+fn huge_waits_are_rejected() {
+    let error = |scenario: &str, options: &RenderOptions| {
+        render(scenario.as_bytes(), options)
+            .err()
+            .unwrap()
+            .to_string()
+    };
+    let options = RenderOptions::default();
+    for scenario in &[
+        "$ a{{pause:1e300}}b\n",
+        "#speed: 1e300\n$ a\n",
+        "#timeout: 1e300\n",
+        "#! {\"step\": 1e300}\n$ a\n",
+    ] {
+        assert!(
+            error(scenario, &options).contains("max_timeout"),
+            "{}",
+            scenario
+        );
+    }
+    let options = RenderOptions {
+        rebase: true,
+        ..RenderOptions::default()
+    };
+    assert!(error("#entry: [1e300, \"o\", \"x\"]\n", &options).contains("max_timeout"));
+    let options = RenderOptions {
+        start_delay: Some(1e300),
+        ..RenderOptions::default()
+    };
+    assert!(error("$ a\n", &options).contains("max_timeout"));
+}
+
+#[test]
+fn huge_header_waits_are_rejected() {
+    for name in &[
+        "pause_gap",
+        "blank_pause",
+        "initial_offset",
+        "output_step",
+        "clear_pre_pause",
+        "clear_post_pause",
+        "prompt_delay",
+        "command_delay",
+        "chain_pause",
+    ] {
+        let scenario = format!("#! {{\"{}\": 1e300}}\n#pause\n", name);
+        let error = render(scenario.as_bytes(), &RenderOptions::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            error.contains(name) && error.contains("max_timeout"),
+            "{}",
+            error
+        );
+    }
+    // the step changed by `#speed:` makes the pause too long
+    let error = render(
+        "#speed: 3000\n#pause\n".as_bytes(),
+        &RenderOptions::default(),
+    )
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(error.contains("`#pause`"), "{}", error);
+}
+
+#[test]
+fn invalid_timeouts_are_rejected() {
+    for duration in &["inf", "NaN", "-1"] {
+        let scenario = format!("$ ls\n#timeout: {}\n", duration);
+        let error = render(scenario.as_bytes(), &RenderOptions::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("line 2: "), "{}", error);
+    }
+}
+
+#[test]
+fn negative_header_numbers_are_rejected() {
+    for name in &[
+        "step",
+        "pause_gap",
+        "idle_time_limit",
+        "slow_factor",
+        "hint_duration",
+    ] {
+        let scenario = format!("#! {{\"{}\": -1}}\n$ a\n", name);
+        assert!(
+            render(scenario.as_bytes(), &RenderOptions::default()).is_err(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn builder_rejects_invalid_numbers() {
+    for seconds in &[f64::NAN, f64::INFINITY, -1.0, 1e300] {
+        assert!(ScenarioBuilder::new().timeout(*seconds).build().is_err());
+        assert!(ScenarioBuilder::new()
+            .step(*seconds)
+            .line_command("ls")
+            .build()
+            .is_err());
+    }
+}
+
+#[test]
+fn bell_rings_the_bell() {
+    let scenario = render_str("#bell\n", &RenderOptions::default());
+    let outputs = events(&scenario.entries, EventType::Output);
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].1, "\u{7}");
+}
+
+#[test]
+fn markdown_output_lines_are_printed_as_is() {
+    let options = RenderOptions {
+        format: InputFormat::Markdown,
+        ..RenderOptions::default()
+    };
+    let markdown =
+        "```console\n$ git diff\n--- a/file\n+++ b/file\n# not a comment\n> quoted\n\n```\n";
+    let scenario = render_str(markdown, &options);
+    let outputs: String = events(&scenario.entries, EventType::Output)
+        .into_iter()
+        .map(|(_, data)| data)
+        .collect();
+    assert!(outputs
+        .ends_with("git diff\r\n--- a/file\r\n+++ b/file\r\n# not a comment\r\n> quoted\r\n\r\n"));
+    assert!(!outputs.contains("\x1b[2J"));
+}
+
+#[test]
+fn toc_lists_later_chapters_and_markers() {
+    let scenario = render_str(
+        "#toc\n#chapter: Setup\n$ ls\n#marker: Install\n$ make\n",
+        &RenderOptions::default(),
+    );
+    let contents: Vec<&str> = scenario
+        .entries
+        .iter()
+        .take_while(|entry| !entry.event_data.contains("\x1b[2J"))
+        .map(|entry| entry.event_data.as_str())
+        .collect();
+    assert_eq!(contents.len(), 3);
+    assert!(contents[0].contains("Contents"));
+    assert!(contents[1].contains("1. Setup"));
+    assert!(contents[2].contains("2. Install"));
+}
+
+/// Render the scenario `name` of the files written into a temporary
+/// directory, giving the error.
+fn include_error(test: &str, files: &[(&str, String)], name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!(
+        "asciinema-scenario-{}-{}",
+        test,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, content) in files {
+        std::fs::write(dir.join(file), content).unwrap();
+    }
+    let path = dir.join(name);
+    let options = RenderOptions {
+        file: Some(path.clone()),
+        ..RenderOptions::default()
+    };
+    let input = std::fs::read(&path).unwrap();
+    let error = render(&input[..], &options).err().unwrap().to_string();
+    std::fs::remove_dir_all(&dir).unwrap();
+    error
+}
+
+#[test]
+fn include_cycles_are_errors() {
+    let error = include_error(
+        "include-self",
+        &[(
+            "self.scenario",
+            "$ ls\n#include: self.scenario\n".to_string(),
+        )],
+        "self.scenario",
+    );
+    assert_eq!(
+        error,
+        "line 2: include cycle, `self.scenario` is already being included"
+    );
+
+    let error = include_error(
+        "include-mutual",
+        &[
+            ("a.scenario", "#include: b.scenario\n".to_string()),
+            ("b.scenario", "$ ls\n#include: a.scenario\n".to_string()),
+        ],
+        "a.scenario",
+    );
+    assert_eq!(
+        error,
+        "line 2 of `b.scenario`, included on line 1: include cycle, `a.scenario` is already being included"
+    );
+}
+
+#[test]
+fn deep_includes_are_errors() {
+    let files: Vec<(String, String)> = (0..20)
+        .map(|index| {
+            (
+                format!("{}.scenario", index),
+                format!("#include: {}.scenario\n", index + 1),
+            )
+        })
+        .collect();
+    let files: Vec<(&str, String)> = files
+        .iter()
+        .map(|(name, content)| (name.as_str(), content.clone()))
+        .collect();
+    let error = include_error("include-deep", &files, "0.scenario");
+    assert!(
+        error.ends_with("`#include:` nested more than 16 times"),
+        "{}",
+        error
+    );
+}
+
+/// The printed output of the entries, without the typed commands.
+fn output_of(scenario: &Scenario) -> String {
+    events(&scenario.entries, EventType::Output)
+        .into_iter()
+        .map(|(_, data)| data)
+        .collect()
+}
+
+#[test]
+fn undefined_variables_are_left_as_is_unless_strict() {
+    let scenario = render_str("echo ${NAME} $HOME\n", &RenderOptions::default());
+    assert_eq!(output_of(&scenario), "echo ${NAME} $HOME\r\n");
+
+    let options = RenderOptions {
+        strict: true,
+        ..RenderOptions::default()
+    };
+    let error = render("$ ls\necho ${NAME}\n".as_bytes(), &options)
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "line 2: undefined variable `NAME`");
+}
+
+#[test]
+fn defined_variables_take_precedence_over_set() {
+    let scenario = "#set: VERSION=1.0\n#set: NAME=demo\n${NAME} ${VERSION}\n";
+    assert_eq!(
+        output_of(&render_str(scenario, &RenderOptions::default())),
+        "demo 1.0\r\n"
+    );
+    let options = RenderOptions {
+        variables: vec![("VERSION".to_string(), "2.0".to_string())],
+        ..RenderOptions::default()
+    };
+    assert_eq!(output_of(&render_str(scenario, &options)), "demo 2.0\r\n");
+}
+
+#[test]
+fn jitter_keeps_times_increasing() {
+    let scenario = "#! {\"idle_time_limit\": 0.2, \"step\": 0.01}\n$ ls -la && pwd\noutput\n#timeout: 1\n$ git{{pause:0.5}} status\n--\n$ echo done\n";
+    for seed in 0..20 {
+        let options = RenderOptions {
+            seed: Some(seed),
+            jitter: 0.9,
+            speed: 1.7,
+            ..RenderOptions::default()
+        };
+        let mut scenario = render_str(scenario, &options);
+        let times: Vec<f64> = scenario.entries.iter().map(|entry| entry.time).collect();
+        assert!(
+            times.windows(2).all(|times| times[0] <= times[1]),
+            "seed {}",
+            seed
+        );
+        scenario.round_times(&OutputOptions {
+            format: CastFormat::V2,
+            time_units: TimeUnits::Seconds,
+            scenario_hash: None,
+            timestamp: None,
+            pretty: false,
+        });
+        let times: Vec<f64> = scenario.entries.iter().map(|entry| entry.time).collect();
+        assert!(
+            times.windows(2).all(|times| times[0] <= times[1]),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+#[test]
+fn streamed_casts_have_the_same_events() {
+    let output_options = OutputOptions {
+        format: CastFormat::V2,
+        time_units: TimeUnits::Seconds,
+        scenario_hash: None,
+        timestamp: None,
+        pretty: false,
+    };
+    let options = RenderOptions {
+        speed: 1.5,
+        preview: false,
+        ..RenderOptions::default()
+    };
+    for scenario in &[
+        "#marker: Start\n$ ls\na b\n#chapter: Next\n$ pwd\n",
+        "#chapter: Intro\n#toc\n$ ls\n#marker: Later\n#chapter: Next\n$ pwd\n",
+        "#! {\"idle_time_limit\": 0.2}\n$ ls\n#timeout: 2\n$ pwd\n",
+    ] {
+        let rendered = render_str(scenario, &options);
+        let mut printed = vec![];
+        print_cast(
+            &mut printed,
+            &rendered.header,
+            &rendered.entries,
+            &output_options,
+        )
+        .unwrap();
+        let mut streamed = vec![];
+        let scenario = stream(
+            scenario.as_bytes(),
+            &options,
+            &mut streamed,
+            &output_options,
+        )
+        .unwrap();
+        assert!(scenario.entries.is_empty());
+        assert!(scenario.preview_lines.is_empty());
+
+        let printed = String::from_utf8(printed).unwrap();
+        let streamed = String::from_utf8(streamed).unwrap();
+        let header = streamed.lines().next().unwrap();
+        assert!(!header.contains("duration"), "{}", header);
+        assert_eq!(
+            streamed.lines().skip(1).collect::<Vec<_>>(),
+            printed.lines().skip(1).collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn assertions_hold_without_the_preview() {
+    let options = RenderOptions {
+        check: true,
+        preview: false,
+        ..RenderOptions::default()
+    };
+    let scenario = "#assert-lines: <= 3\n$ ls\na b\n--\n$ pwd\n/tmp\n";
+    render_str(scenario, &options);
+    let error = render("#assert-lines: <= 1\n$ ls\na b\n".as_bytes(), &options).unwrap_err();
+    assert!(error.to_string().contains("#assert-lines:"));
+    let error = render("#assert-width: <= 3\n$ ls\nabcd\n".as_bytes(), &options).unwrap_err();
+    assert!(error.to_string().contains("#assert-width:"));
+}